@@ -0,0 +1,372 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::{
+    config::{
+        cloud_config::CloudConfig,
+        loading::{diagnostics::Diagnostics, load_configs, LoadOptions},
+    },
+    tf_generation::{self, OutputFormat},
+};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "instacloud",
+    version,
+    about = "Generates cloud infrastructure from an InstaCloud project"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ProjectArgs {
+    /// Directory containing the project's cloud.toml
+    #[arg(default_value = ".")]
+    pub project: PathBuf,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConfigArgs {
+    /// Sets a config variable, overriding vars.toml, .env and the environment
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+    pub vars: Vec<(String, String)>,
+}
+
+impl ConfigArgs {
+    pub fn load(&self, project: &ProjectArgs, env: &EnvArgs) -> Result<CloudConfig, Diagnostics> {
+        load_configs(
+            &project.project,
+            &LoadOptions {
+                vars: self.vars.clone(),
+                environment: env.env.clone(),
+            },
+        )
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct EnvArgs {
+    /// The environment (e.g. dev, staging, prod) to use, applying cloud.<env>.toml on top of cloud.toml
+    #[arg(long)]
+    pub env: Option<String>,
+}
+
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{s}`"))
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct OutputArgs {
+    /// Directory the generated terraform and lambda zips are written to.
+    ///
+    /// Defaults to `<project>/terraform/generated`, or `<project>/terraform/generated/<env>` when
+    /// an environment is selected.
+    #[arg(short, long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FormatArgs {
+    /// Write terraform's HCL syntax (main.tf) or its JSON syntax (main.tf.json)
+    #[arg(long, value_enum, default_value_t = Format::Hcl)]
+    pub format: Format,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Format {
+    Hcl,
+    Json,
+}
+
+impl From<Format> for OutputFormat {
+    fn from(value: Format) -> Self {
+        match value {
+            Format::Hcl => Self::Hcl,
+            Format::Json => Self::Json,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum GraphFormat {
+    Text,
+    Dot,
+    Mermaid,
+}
+
+impl OutputArgs {
+    pub fn out_dir(&self, project: &ProjectArgs, env: &EnvArgs) -> PathBuf {
+        self.out_dir_named(project, env, "generated")
+    }
+
+    /// `--out`, or `<project>/terraform/<name>[/<env>]`
+    pub fn out_dir_named(&self, project: &ProjectArgs, env: &EnvArgs, name: &str) -> PathBuf {
+        self.out.clone().unwrap_or_else(|| {
+            let dir = project.project.join("terraform").join(name);
+            match &env.env {
+                Some(env) => dir.join(env),
+                None => dir,
+            }
+        })
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Creates a new project containing a sample cloud.toml and endpoint
+    Init {
+        #[command(flatten)]
+        project: ProjectArgs,
+    },
+    /// Generates the terraform and lambda zips for a project
+    Build {
+        #[command(flatten)]
+        project: ProjectArgs,
+        #[command(flatten)]
+        env: EnvArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// Generates the terraform for the S3 bucket + DynamoDB table that hold the project's state.
+    ///
+    /// Written to `<project>/terraform/bootstrap` by default. Apply it once before using an S3
+    /// backend without a `bucket`.
+    Bootstrap {
+        #[command(flatten)]
+        project: ProjectArgs,
+        #[command(flatten)]
+        env: EnvArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// Loads a project's configuration and reports any problems without writing anything
+    Validate {
+        #[command(flatten)]
+        project: ProjectArgs,
+        #[command(flatten)]
+        env: EnvArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Compares what a build would generate with the last build in the output directory, and
+    /// prints the resources that would be created, changed, replaced or destroyed
+    Plan {
+        #[command(flatten)]
+        project: ProjectArgs,
+        #[command(flatten)]
+        env: EnvArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Removes previously generated files
+    Clean {
+        #[command(flatten)]
+        project: ProjectArgs,
+        #[command(flatten)]
+        env: EnvArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Prints the resources a project would generate
+    Graph {
+        #[command(flatten)]
+        project: ProjectArgs,
+        #[command(flatten)]
+        env: EnvArgs,
+        #[command(flatten)]
+        config: ConfigArgs,
+        /// Print the APIs + endpoints as text, or every generated resource and the references
+        /// between them as a Graphviz (dot) or Mermaid graph
+        #[arg(long, value_enum, default_value_t = GraphFormat::Text)]
+        format: GraphFormat,
+    },
+}
+
+impl Cli {
+    pub fn run(self) -> anyhow::Result<()> {
+        match self.command {
+            Command::Init { project } => init(&project.project),
+            Command::Build {
+                project,
+                env,
+                config,
+                output,
+                format,
+            } => {
+                let config = config.load(&project, &env)?;
+                let out_dir = output.out_dir(&project, &env);
+
+                let edited = tf_generation::generate(&config, &out_dir, format.format.into())?;
+                print_edited_files(&out_dir, &edited);
+
+                println!("Generated terraform in {}", out_dir.display());
+                Ok(())
+            }
+            Command::Bootstrap {
+                project,
+                env,
+                config,
+                output,
+                format,
+            } => {
+                let config = config.load(&project, &env)?;
+                let out_dir = output.out_dir_named(&project, &env, "bootstrap");
+
+                let edited = tf_generation::bootstrap::generate_bootstrap(
+                    &config,
+                    &out_dir,
+                    format.format.into(),
+                )?;
+                print_edited_files(&out_dir, &edited);
+
+                println!(
+                    "Generated the state bootstrap terraform in {}",
+                    out_dir.display()
+                );
+                Ok(())
+            }
+            Command::Validate {
+                project,
+                env,
+                config,
+            } => {
+                let config = config.load(&project, &env)?;
+                tf_generation::create_terraform(&config)?;
+
+                println!("{} is valid", project.project.display());
+                Ok(())
+            }
+            Command::Plan {
+                project,
+                env,
+                config,
+                output,
+            } => {
+                let config = config.load(&project, &env)?;
+                let out_dir = output.out_dir(&project, &env);
+
+                print!("{}", tf_generation::plan::plan(&config, &out_dir)?);
+                Ok(())
+            }
+            Command::Clean {
+                project,
+                env,
+                output,
+            } => {
+                let out_dir = output.out_dir(&project, &env);
+                let edited = tf_generation::clean(&out_dir)?;
+                print_edited_files(&out_dir, &edited);
+
+                println!("Removed generated files from {}", out_dir.display());
+                Ok(())
+            }
+            Command::Graph {
+                project,
+                env,
+                config,
+                format,
+            } => {
+                let config = config.load(&project, &env)?;
+
+                match format {
+                    GraphFormat::Text => print_apis(&config),
+                    GraphFormat::Dot => {
+                        let (_, terraform) = tf_generation::create_terraform(&config)?;
+                        print!("{}", terraform.graph().to_dot());
+                    }
+                    GraphFormat::Mermaid => {
+                        let (_, terraform) = tf_generation::create_terraform(&config)?;
+                        print!("{}", terraform.graph().to_mermaid());
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Generated files that are no longer generated, but weren't removed since they've been edited
+fn print_edited_files(out_dir: &Path, edited: &[String]) {
+    for file in edited {
+        eprintln!(
+            "Left {} in place since it was edited after it was generated",
+            out_dir.join(file).display()
+        );
+    }
+}
+
+fn print_apis(config: &CloudConfig) {
+    for api in config.api.iter() {
+        println!(
+            "api {} ({})",
+            api.name,
+            api.domain.as_deref().unwrap_or("no custom domain")
+        );
+        for endpoint in api.endpoints.iter() {
+            let method: String = endpoint.method.into();
+            println!(
+                "  {method} {} -> {}",
+                api.prefix.join(&endpoint.route),
+                endpoint.file.display()
+            );
+        }
+    }
+}
+
+const INIT_CLOUD_TOML: &str = r#"[[api]]
+name = "main"
+root = "api"
+domain = "api.example.com"
+"#;
+
+const INIT_ENDPOINTS_TOML: &str = r#"[[api]]
+name = "get_hello"
+method = "get"
+route = "hello"
+file = "get-hello.js"
+"#;
+
+const INIT_HANDLER_JS: &str = r#"exports.handler = async (params) => {
+  return {
+    statusCode: 200,
+    body: JSON.stringify("Hello, World!"),
+  };
+};
+"#;
+
+fn init(project: &Path) -> anyhow::Result<()> {
+    let cloud_toml = project.join("cloud.toml");
+    if cloud_toml.exists() {
+        bail!("{} already exists", cloud_toml.display());
+    }
+
+    let hello_dir = project.join("api").join("hello");
+    fs::create_dir_all(&hello_dir)?;
+
+    fs::write(cloud_toml, INIT_CLOUD_TOML)?;
+    fs::write(hello_dir.join("endpoints.toml"), INIT_ENDPOINTS_TOML)?;
+    fs::write(hello_dir.join("get-hello.js"), INIT_HANDLER_JS)?;
+
+    println!("Created a new project in {}", project.display());
+    Ok(())
+}
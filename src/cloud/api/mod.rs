@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -52,13 +54,187 @@ impl<'a> TryFrom<&'a str> for HttpMethod {
     }
 }
 
-pub struct ApiEndpoint {
-    name: String,
-    method: HttpMethod,
-    route: String,
-    file: String,
-    read: Vec<String>,
-    write: Vec<String>,
+/// How an API is exposed - see [`crate::stack::api::domain_name::EndpointConfiguration`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EndpointType {
+    #[default]
+    Regional,
+    Edge,
+    Private,
+}
+
+impl Display for EndpointType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Regional => "regional",
+            Self::Edge => "edge",
+            Self::Private => "private",
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a str> for EndpointType {
+    type Error = ApiDefinitionError;
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "regional" => Ok(Self::Regional),
+            "edge" => Ok(Self::Edge),
+            "private" => Ok(Self::Private),
+            _ => Err(ApiDefinitionError::InvalidEndpointType(value.to_owned())),
+        }
+    }
+}
+
+/// One `/`-separated part of a route
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PathSegment {
+    /// Only matches itself, e.g. `posts`
+    Literal(String),
+    /// `{id}` - matches any single segment, passed to the handler as the `id` path parameter
+    Parameter(String),
+    /// `{proxy+}` - matches the rest of the path, however many segments that is
+    Greedy(String),
+}
+
+impl From<&str> for PathSegment {
+    fn from(value: &str) -> Self {
+        match value.strip_prefix('{').and_then(|x| x.strip_suffix('}')) {
+            Some(name) => match name.strip_suffix('+') {
+                Some(name) => Self::Greedy(name.to_owned()),
+                None => Self::Parameter(name.to_owned()),
+            },
+            None => Self::Literal(value.to_owned()),
+        }
+    }
+}
+
+impl Display for PathSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Literal(s) => write!(f, "{s}"),
+            Self::Parameter(name) => write!(f, "{{{name}}}"),
+            Self::Greedy(name) => write!(f, "{{{name}+}}"),
+        }
+    }
+}
+
+impl PathSegment {
+    /// A directory in an API's root named `[id]` is the `{id}` parameter, and `[...path]` the
+    /// greedy `{path+}` parameter
+    pub fn from_directory_name(name: &str) -> Self {
+        match name.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+            Some(name) => match name.strip_prefix("...") {
+                Some(name) => Self::Greedy(name.to_owned()),
+                None => Self::Parameter(name.to_owned()),
+            },
+            None => Self::Literal(name.to_owned()),
+        }
+    }
+
+    /// The name of the path parameter this segment sets, if any
+    pub fn parameter_name(&self) -> Option<&str> {
+        match self {
+            Self::Literal(_) => None,
+            Self::Parameter(name) | Self::Greedy(name) => Some(name),
+        }
+    }
+
+    /// API gateway path parts may only contain letters, numbers, and `-._~:`, and parameter names
+    /// letters, numbers and `_`
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Self::Literal(s) => {
+                !s.is_empty()
+                    && s.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-._~:".contains(c))
+            }
+            Self::Parameter(name) | Self::Greedy(name) => {
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            }
+        }
+    }
+
+    /// This segment as part of a terraform identifier, e.g. `param_id` for `{id}`
+    pub fn identifier_part(&self) -> String {
+        match self {
+            Self::Literal(s) => s.clone(),
+            Self::Parameter(name) => format!("param_{name}"),
+            Self::Greedy(name) => format!("greedy_{name}"),
+        }
+    }
+}
+
+/// A path on an API, e.g. `/posts/{id}`, as the segments between each `/`. The root of the API
+/// has no segments.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Route(pub Vec<PathSegment>);
+
+impl Route {
+    /// Leading, trailing and repeated `/`s don't matter, so `/posts/`, `posts` and `posts//` are
+    /// all the same route
+    pub fn parse(route: &str) -> Self {
+        Self(
+            route
+                .split('/')
+                .filter(|x| !x.is_empty())
+                .map(PathSegment::from)
+                .collect(),
+        )
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The last segment, unless this is the root
+    pub fn last(&self) -> Option<&PathSegment> {
+        self.0.last()
+    }
+
+    /// This route with `segment` on the end
+    pub fn child(&self, segment: PathSegment) -> Self {
+        let mut segments = self.0.clone();
+        segments.push(segment);
+        Self(segments)
+    }
+
+    /// `other` under this route, e.g. `/api` + `/posts` is `/api/posts`
+    pub fn join(&self, other: &Route) -> Self {
+        Self(self.0.iter().chain(other.0.iter()).cloned().collect())
+    }
+
+    /// The route without its leading `/`, e.g. `posts/{id}` (or an empty string for the root)
+    pub fn path(&self) -> String {
+        self.0
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Every segment has to be valid (see [`PathSegment::is_valid`]), and a greedy `{proxy+}`
+    /// parameter can only be the last one
+    pub fn is_valid(&self) -> bool {
+        self.0.iter().enumerate().all(|(i, segment)| {
+            segment.is_valid()
+                && (i == self.0.len() - 1 || !matches!(segment, PathSegment::Greedy(_)))
+        })
+    }
+
+    /// The names of the `{param}`s in this route
+    pub fn parameter_names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(PathSegment::parameter_name)
+    }
+}
+
+impl Display for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "/{}", self.path())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -76,12 +252,112 @@ pub struct ApiDefinition {
 pub enum ApiDefinitionError {
     #[error("Invalid API method: {0}")]
     InvalidMethod(String),
-    #[error("API definitions contain duplicate name")]
-    DuplicateNameFound,
-    #[error("Not a valid API path")]
-    InvalidPath,
+    #[error("API definitions contain duplicate name: {0}")]
+    DuplicateNameFound(String),
+    #[error("Not a valid API path: {0}")]
+    InvalidPath(String),
+    #[error("Path parameter {{{0}}} is used more than once in {1}")]
+    DuplicatePathParameter(String, String),
+    #[error("{route} uses the path parameter {found} where other routes use {existing} - every route has to use the same parameter name at the same position")]
+    ConflictingPathParameters {
+        route: String,
+        existing: String,
+        found: String,
+    },
+    #[error("Invalid endpoint type: {0} (expected regional, edge or private)")]
+    InvalidEndpointType(String),
+    #[error("Private APIs need at least one VPC endpoint in vpc_endpoint_ids")]
+    MissingVpcEndpoints,
+    #[error("Only private APIs are restricted to VPC endpoints, but this API is {0}")]
+    UnexpectedVpcEndpoints(EndpointType),
+    #[error("Private APIs can't be served from a custom domain")]
+    PrivateApiWithDomain,
+    #[error("APIs on {domain} have to use the same endpoint type, but this one is {found} and others are {existing}")]
+    ConflictingEndpointTypes {
+        domain: String,
+        existing: EndpointType,
+        found: EndpointType,
+    },
+    #[error("{0} has more than one segment, which APIs on a custom domain can't be served from - use a single segment such as /v1")]
+    NestedBasePath(String),
+    #[error("Another API is already served from {0}")]
+    DuplicateBasePath(String),
+    #[error("Another endpoint already handles {0}")]
+    DuplicateRoute(String),
+    #[error("Handler file {0} does not exist")]
+    MissingHandler(String),
+    #[error("{0} is not a declared resource")]
+    UndeclaredResource(String),
     #[error("Cannot read TOML file: {0}")]
     CannotReadTomlFile(std::io::Error),
     #[error("Cannot parse TOML file: {0}")]
-    CannotParseTomlFile(toml::de::Error),
+    CannotParseTomlFile(String),
+    #[error("API root folder {0} does not exist")]
+    MissingRoot(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_segments() {
+        assert_eq!(
+            PathSegment::from("posts"),
+            PathSegment::Literal("posts".into())
+        );
+        assert_eq!(
+            PathSegment::from("{id}"),
+            PathSegment::Parameter("id".into())
+        );
+        assert_eq!(
+            PathSegment::from("{proxy+}"),
+            PathSegment::Greedy("proxy".into())
+        );
+        assert_eq!(PathSegment::from("{id"), PathSegment::Literal("{id".into()));
+    }
+
+    #[test]
+    fn parses_directory_names() {
+        assert_eq!(
+            PathSegment::from_directory_name("posts"),
+            PathSegment::Literal("posts".into())
+        );
+        assert_eq!(
+            PathSegment::from_directory_name("[id]"),
+            PathSegment::Parameter("id".into())
+        );
+        assert_eq!(
+            PathSegment::from_directory_name("[...path]"),
+            PathSegment::Greedy("path".into())
+        );
+        assert_eq!(
+            PathSegment::from_directory_name("[id"),
+            PathSegment::Literal("[id".into())
+        );
+    }
+
+    #[test]
+    fn validates_routes() {
+        assert!(Route::parse("/posts/{id}/comments").is_valid());
+        assert!(Route::parse("files/{path+}").is_valid());
+        assert!(Route::parse("").is_valid());
+
+        assert!(!Route::parse("files/{path+}/edit").is_valid());
+        assert!(!Route::parse("posts/{}").is_valid());
+        assert!(!Route::parse("posts/{post-id}").is_valid());
+        assert!(!Route::parse("my posts").is_valid());
+    }
+
+    #[test]
+    fn normalizes_routes() {
+        assert_eq!(Route::parse("/posts/"), Route::parse("posts"));
+        assert_eq!(Route::parse("posts//{id}"), Route::parse("/posts/{id}"));
+        assert!(Route::parse("/").is_root());
+
+        let route = Route::parse("api").join(&Route::parse("/posts/{id}"));
+        assert_eq!(route.to_string(), "/api/posts/{id}");
+        assert_eq!(route.path(), "api/posts/{id}");
+        assert_eq!(Route::default().to_string(), "/");
+    }
 }
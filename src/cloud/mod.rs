@@ -1,14 +1,165 @@
-use api::ApiEndpoint;
+use crate::stack::{
+    api::{
+        domain_name::{Certificate, Domain, DomainEndpoint},
+        gateway::ApiGateway,
+    },
+    iam::role::{Role, RolePolicy, RoleService},
+    region::Provider,
+    registry::{Stack, StackError},
+    settings::TerraformSettings,
+    shared,
+    tf::{identifier::Identifiers, SensitiveOutputs, Terraform},
+    Shared,
+};
 
 pub mod api;
 
 #[derive(Default)]
 pub struct Cloud {
-    apis: Vec<ApiEndpoint>,
+    environment: Option<String>,
+    settings: Option<TerraformSettings>,
+    provider: Provider,
+    roles: Vec<Shared<Role>>,
+    domains: Vec<Shared<Domain>>,
+    gateways: Vec<ApiGateway>,
+    identifiers: Identifiers,
+    sensitive_outputs: SensitiveOutputs,
 }
 
 impl Cloud {
-    pub fn set_apis(&mut self, apis: Vec<ApiEndpoint>) {
-        self.apis = apis;
+    pub fn new(environment: Option<String>) -> Self {
+        Self {
+            environment,
+            ..Default::default()
+        }
+    }
+
+    /// The AWS region everything is deployed to
+    pub fn region(&self) -> &'static str {
+        self.provider.region.to_tf_string()
+    }
+
+    pub fn set_terraform_settings(&mut self, settings: TerraformSettings) {
+        self.settings = Some(settings);
+    }
+
+    /// Sets the outputs to hide in terraform's plan + apply output. Resources added before this
+    /// keep the outputs they had.
+    pub fn set_sensitive_outputs(&mut self, outputs: SensitiveOutputs) {
+        self.sensitive_outputs = outputs;
+    }
+
+    pub fn sensitive_outputs(&self) -> &SensitiveOutputs {
+        &self.sensitive_outputs
+    }
+
+    /// The API gateway stage every API is deployed to
+    pub fn stage_name(&self) -> &str {
+        self.environment.as_deref().unwrap_or("prod")
+    }
+
+    /// Names a resource so copies of the stack deployed to other environments don't collide with it
+    pub fn resource_name(&self, name: &str) -> String {
+        match &self.environment {
+            Some(environment) => format!("{name}_{environment}"),
+            None => name.to_owned(),
+        }
+    }
+
+    /// [`Self::resource_name`], for names made of several parts
+    pub fn resource_name_parts(&self, parts: &[&str]) -> Vec<String> {
+        parts
+            .iter()
+            .copied()
+            .chain(self.environment.as_deref())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// The names of everything in this cloud that are made into terraform identifiers
+    pub fn identifiers(&self) -> &Identifiers {
+        &self.identifiers
+    }
+
+    pub fn add_gateway(&mut self, gateway: ApiGateway) {
+        gateway.register_identifiers();
+        self.gateways.push(gateway);
+    }
+
+    pub fn gateways(&self) -> &[ApiGateway] {
+        &self.gateways
+    }
+
+    /// Gets the custom domain for this domain name, creating it (and its certificate) if no other
+    /// resource has used it yet. Every API on a domain has the same endpoint type, so it's only
+    /// used when the domain is created.
+    pub fn domain(&mut self, domain_name: &str, endpoint: DomainEndpoint) -> Shared<Domain> {
+        if let Some(domain) = self
+            .domains
+            .iter()
+            .find(|x| x.borrow().certificate.borrow().domain == domain_name)
+        {
+            return domain.clone();
+        }
+
+        let certificate = shared(Certificate::new(domain_name, &self.identifiers));
+
+        let domain = shared(Domain {
+            endpoint,
+            certificate,
+            sensitive_outputs: self.sensitive_outputs.clone(),
+        });
+        self.domains.push(domain.clone());
+
+        domain
+    }
+
+    /// Gets the role every lambda function is executed as
+    pub fn lambda_role(&mut self) -> Shared<Role> {
+        let role_name = self.resource_name("LambdaRole");
+
+        if let Some(role) = self.roles.iter().find(|x| x.borrow().name == role_name) {
+            return role.clone();
+        }
+
+        let role = shared(Role::new(
+            role_name,
+            vec![RolePolicy::new(RoleService::Lambda)],
+            &self.identifiers,
+            &self.sensitive_outputs,
+        ));
+        self.roles.push(role.clone());
+
+        role
+    }
+
+    /// The zip file of every lambda, by file name
+    pub fn zip_lambdas(&self) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        self.gateways
+            .iter()
+            .flat_map(|x| x.endpoints.iter())
+            .map(|endpoint| Ok((endpoint.lambda.zip_path(), endpoint.zip()?)))
+            .collect()
+    }
+
+    /// Collects everything in this cloud into a stack. Roles, domains and certificates are added
+    /// by the gateways that use them.
+    pub fn create_stack(&self) -> Stack {
+        let mut stack = Stack::new();
+
+        if let Some(settings) = &self.settings {
+            stack.add(settings.clone());
+        }
+        stack.add(self.provider.clone());
+
+        for gateway in self.gateways.iter() {
+            stack.add(gateway.clone());
+        }
+
+        stack
+    }
+
+    pub fn create_terraform(&self) -> Result<Terraform, StackError> {
+        self.create_stack().render()
     }
 }
@@ -1,22 +1,38 @@
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 
-use crate::cloud::api::HttpMethod;
+use crate::{
+    cloud::api::{EndpointType, HttpMethod, Route},
+    config::loading::diagnostics::SourceRef,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ApiConfig {
     pub name: String,
-    pub root: String,
-    pub domain: String,
-    pub prefix: Option<String>,
+    pub root: PathBuf,
+    /// The custom domain the API is served from. Without one, it's served from the stage's
+    /// `execute-api` URL.
+    pub domain: Option<String>,
+    /// Where the API is served from on its domain, e.g. `/api` for `https://example.com/api/posts`
+    pub prefix: Route,
+    pub endpoint_type: EndpointType,
+    /// The VPC endpoints a private API can be invoked through
+    pub vpc_endpoint_ids: Vec<String>,
     pub endpoints: Vec<ApiEndpoint>,
+    /// Where this `[[api]]` was declared
+    pub source: SourceRef,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ApiEndpoint {
     pub name: String,
     pub method: HttpMethod,
-    pub route: String,
-    pub file: String,
+    /// Relative to the API's prefix
+    pub route: Route,
+    pub file: PathBuf,
     pub read: Vec<String>,
     pub write: Vec<String>,
+    /// Where this endpoint was declared
+    pub source: SourceRef,
 }
@@ -1,20 +1,56 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 use serde::{Deserialize, Serialize};
 
-use super::{api::api_config::ApiConfig, loading::api::ApiConfigRaw};
+use super::{
+    api::api_config::ApiConfig,
+    database::database_config::DatabaseConfig,
+    loading::{
+        api::{ApiConfigRaw, ApiOverlayRaw},
+        database::DatabaseConfigRaw,
+    },
+    outputs::OutputsConfig,
+    terraform::TerraformConfig,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct CloudConfigRaw {
+    pub project: Option<ProjectConfigRaw>,
     pub api: Option<Vec<ApiConfigRaw>>,
+    pub database: Option<Vec<DatabaseConfigRaw>>,
+    pub outputs: Option<OutputsConfig>,
+    pub terraform: Option<TerraformConfig>,
+}
+
+/// The `[project]` table of `cloud.toml`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProjectConfigRaw {
+    /// Defaults to the name of the project's directory
+    pub name: Option<String>,
+}
+
+/// The contents of a `cloud.<env>.toml`, which is layered on top of `cloud.toml` for that environment
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CloudOverlayRaw {
+    pub vars: Option<BTreeMap<String, toml::Value>>,
+    pub api: Option<Vec<ApiOverlayRaw>>,
+    /// Replaces the whole `[terraform]` table, e.g. to keep each environment's state separately
+    pub terraform: Option<TerraformConfig>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CloudConfig {
+    /// Used to name resources shared by the whole project, like its terraform state bucket
+    pub project_name: String,
+    /// The environment (dev, staging, prod, etc) being deployed, if one was selected
+    pub environment: Option<String>,
     pub api: Vec<ApiConfig>,
+    pub database: Vec<DatabaseConfig>,
+    pub outputs: OutputsConfig,
+    pub terraform: TerraformConfig,
 }
 
-trait ParseConfig {
+pub trait ParseConfig {
     type Output;
     type Error;
 
@@ -28,12 +64,10 @@ pub trait NameUniquenessChecker {
 
 impl<'a, T: Iterator<Item = &'a str> + Clone> NameUniquenessChecker for T {
     fn check_all_unique_names(self) -> bool {
-        let len = self.clone().into_iter().count();
+        let len = self.clone().count();
 
         let set = self.map(|x| x.to_lowercase()).collect::<HashSet<String>>();
 
         set.len() == len
     }
 }
-
-impl CloudConfig {}
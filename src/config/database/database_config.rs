@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::loading::diagnostics::SourceRef;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseConfig {
+    pub name: String,
+    pub engine: String,
+    /// Where this `[[database]]` was declared
+    pub source: SourceRef,
+}
@@ -1,24 +1,36 @@
-use std::fs;
+use std::path::Path;
 
 use serde::{Deserialize, Serialize};
+use toml::Spanned;
 use walkdir::WalkDir;
 
 use crate::{
-    cloud::api::ApiDefinitionError,
+    cloud::api::{ApiDefinitionError, EndpointType, PathSegment, Route},
     config::{
         api::api_config::{ApiConfig, ApiEndpoint},
-        ConfigVariables, ContainsVariables,
+        ConfigVariableError, ConfigVariables, ContainsVariables,
     },
 };
 
+use super::diagnostics::{Diagnostic, Diagnostics, SourceFile, SourceRef};
+
 pub fn create_api_definitions(
     api_config: &ApiConfig,
     vars: &ConfigVariables,
-) -> Result<Vec<ApiEndpoint>, ApiDefinitionError> {
+    diagnostics: &mut Diagnostics,
+) -> Vec<ApiEndpoint> {
     let mut result = vec![];
 
-    let base_path = &api_config.root;
-    for item in WalkDir::new(base_path).into_iter().filter_map(|x| x.ok()) {
+    let root = &api_config.root;
+    for item in WalkDir::new(root) {
+        let item = match item {
+            Ok(item) => item,
+            Err(e) => {
+                let path = e.path().unwrap_or(root).to_owned();
+                diagnostics.push(Diagnostic::new(path, e));
+                continue;
+            }
+        };
         let item_path = item.path();
 
         if item_path.is_dir() {
@@ -29,63 +41,144 @@ pub fn create_api_definitions(
             continue;
         }
 
-        let item_path_str = item_path.to_str().unwrap();
+        let item_dir = item_path.parent().unwrap_or(root);
 
-        let data = toml::from_str::<ApiEndpointsRaw>(
-            &fs::read_to_string(item.file_name())
-                .map_err(|e| ApiDefinitionError::CannotReadTomlFile(e))?,
-        )
-        .map_err(|e| ApiDefinitionError::CannotParseTomlFile(e))?;
+        let Some(source) = SourceFile::load(item_path, diagnostics) else {
+            continue;
+        };
+        let Some(data) = source.parse::<ApiEndpointsRaw>(diagnostics) else {
+            continue;
+        };
 
         for mut x in data.api {
-            x.replace_variables(vars);
+            let mut errors = vec![];
+            x.replace_variables(vars, &mut errors);
+            for e in errors {
+                diagnostics.push(Diagnostic::new(item_path, e));
+            }
+
+            let method = match x.method.as_ref().as_str().try_into() {
+                Ok(method) => method,
+                Err(_) => {
+                    diagnostics.push(
+                        source
+                            .diagnostic(
+                                Some(x.method.span()),
+                                ApiDefinitionError::InvalidMethod(x.method.get_ref().clone()),
+                            )
+                            .with_key("method"),
+                    );
+                    continue;
+                }
+            };
+
+            // Directories named `[id]` are path parameters
+            let default_route = Route(
+                item_dir
+                    .strip_prefix(root)
+                    .unwrap_or(item_dir)
+                    .components()
+                    .map(|x| PathSegment::from_directory_name(&x.as_os_str().to_string_lossy()))
+                    .collect(),
+            );
 
             result.push(ApiEndpoint {
                 read: x.read.unwrap_or_default(),
                 write: x.write.unwrap_or_default(),
-                name: x.name,
-                file: x.file,
-                route: format!(
-                    "{}/{}",
-                    api_config.prefix.as_ref().map(|x| x.as_str()).unwrap_or(""),
-                    x.route
-                        .as_ref()
-                        .map(|x| x.as_str())
-                        .unwrap_or(&item_path_str[base_path.len()..])
-                ),
-                method: x
-                    .method
-                    .as_str()
-                    .try_into()
-                    .map_err(|_| ApiDefinitionError::InvalidMethod(x.method.clone()))?,
+                source: source.source_ref(Some(x.name.span())),
+                name: x.name.into_inner(),
+                file: item_dir.join(&x.file),
+                route: x
+                    .route
+                    .as_deref()
+                    .map(Route::parse)
+                    .unwrap_or(default_route),
+                method,
             });
         }
     }
 
-    Ok(result)
+    result
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ApiConfigRaw {
-    pub name: String,
-    pub root: String,
-    pub domain: String,
+    pub name: Spanned<String>,
+    pub root: Spanned<String>,
+    pub domain: Option<String>,
     pub prefix: Option<String>,
+    pub endpoint_type: Option<Spanned<String>>,
+    pub vpc_endpoint_ids: Option<Vec<String>>,
+    /// Where `endpoint_type` was set, if it was overridden by an environment's overlay rather than
+    /// set in `cloud.toml`
+    #[serde(skip)]
+    pub endpoint_type_source: Option<SourceRef>,
 }
 
 impl ContainsVariables for ApiConfigRaw {
-    fn replace_variables(&mut self, vars: &ConfigVariables) {
-        self.name.replace_variables(vars);
-        self.root.replace_variables(vars);
-        self.domain.replace_variables(vars);
-        self.prefix.replace_variables(vars);
+    fn replace_variables(&mut self, vars: &ConfigVariables, errors: &mut Vec<ConfigVariableError>) {
+        self.name.replace_variables(vars, errors);
+        self.root.replace_variables(vars, errors);
+        self.domain.replace_variables(vars, errors);
+        self.prefix.replace_variables(vars, errors);
+        self.endpoint_type.replace_variables(vars, errors);
+        self.vpc_endpoint_ids.replace_variables(vars, errors);
+    }
+}
+
+/// Per-environment overrides for the `[[api]]` with the same name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApiOverlayRaw {
+    pub name: Spanned<String>,
+    pub domain: Option<String>,
+    pub prefix: Option<String>,
+    pub endpoint_type: Option<Spanned<String>>,
+    pub vpc_endpoint_ids: Option<Vec<String>>,
+}
+
+/// Replaces the fields of each `[[api]]` with the ones set in the environment's overlay
+pub fn apply_api_overlays(
+    apis: &mut [ApiConfigRaw],
+    overlays: Vec<ApiOverlayRaw>,
+    overlay_toml: &SourceFile,
+    diagnostics: &mut Diagnostics,
+) {
+    for overlay in overlays {
+        let Some(api) = apis
+            .iter_mut()
+            .find(|x| x.name.get_ref() == overlay.name.get_ref())
+        else {
+            diagnostics.push(
+                overlay_toml
+                    .diagnostic(
+                        Some(overlay.name.span()),
+                        format!("No [[api]] named {} in cloud.toml", overlay.name.get_ref()),
+                    )
+                    .with_key("name"),
+            );
+            continue;
+        };
+
+        if let Some(domain) = overlay.domain {
+            api.domain = Some(domain);
+        }
+        if let Some(prefix) = overlay.prefix {
+            api.prefix = Some(prefix);
+        }
+        if let Some(endpoint_type) = overlay.endpoint_type {
+            api.endpoint_type_source = Some(overlay_toml.source_ref(Some(endpoint_type.span())));
+            api.endpoint_type = Some(endpoint_type);
+        }
+        if let Some(vpc_endpoint_ids) = overlay.vpc_endpoint_ids {
+            api.vpc_endpoint_ids = Some(vpc_endpoint_ids);
+        }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ApiEndpointRaw {
-    name: String,
-    method: String,
+    name: Spanned<String>,
+    method: Spanned<String>,
     route: Option<String>,
     file: String,
     read: Option<Vec<String>>,
@@ -93,13 +186,13 @@ pub struct ApiEndpointRaw {
 }
 
 impl ContainsVariables for ApiEndpointRaw {
-    fn replace_variables(&mut self, vars: &ConfigVariables) {
-        self.method.replace_variables(vars);
-        self.route.replace_variables(vars);
-        self.file.replace_variables(vars);
-        self.name.replace_variables(vars);
-        self.read.replace_variables(vars);
-        self.write.replace_variables(vars);
+    fn replace_variables(&mut self, vars: &ConfigVariables, errors: &mut Vec<ConfigVariableError>) {
+        self.method.replace_variables(vars, errors);
+        self.route.replace_variables(vars, errors);
+        self.file.replace_variables(vars, errors);
+        self.name.replace_variables(vars, errors);
+        self.read.replace_variables(vars, errors);
+        self.write.replace_variables(vars, errors);
     }
 }
 
@@ -109,27 +202,194 @@ pub struct ApiEndpointsRaw {
 }
 
 pub fn load_raw_api_config(
-    raw: ApiConfigRaw,
+    base_path: &Path,
+    cloud_toml: &SourceFile,
+    mut raw: ApiConfigRaw,
     vars: &ConfigVariables,
-) -> Result<ApiConfig, ApiDefinitionError> {
+    diagnostics: &mut Diagnostics,
+) -> ApiConfig {
+    let mut errors = vec![];
+    raw.replace_variables(vars, &mut errors);
+    for e in errors {
+        diagnostics.push(cloud_toml.diagnostic(Some(raw.name.span()), e));
+    }
+
+    let endpoint_type = match raw.endpoint_type.as_ref() {
+        Some(x) => x.get_ref().as_str().try_into().unwrap_or_else(|e| {
+            let diagnostic = match &raw.endpoint_type_source {
+                Some(source) => Diagnostic::at(source, e),
+                None => cloud_toml.diagnostic(Some(x.span()), e),
+            };
+            diagnostics.push(diagnostic.with_key("endpoint_type"));
+            EndpointType::default()
+        }),
+        None => EndpointType::default(),
+    };
+
     let mut api_def = ApiConfig {
-        name: raw.name,
+        source: cloud_toml.source_ref(Some(raw.name.span())),
+        name: raw.name.into_inner(),
         endpoints: vec![],
-        domain: raw.domain,
-        root: raw.root,
-        prefix: raw.prefix,
+        // An empty domain (e.g. `${domain:-}`) lets environments go without one
+        domain: raw.domain.filter(|x| !x.is_empty()),
+        root: base_path.join(raw.root.get_ref()),
+        prefix: raw.prefix.as_deref().map(Route::parse).unwrap_or_default(),
+        endpoint_type,
+        vpc_endpoint_ids: raw.vpc_endpoint_ids.unwrap_or_default(),
     };
 
-    api_def.endpoints = create_api_definitions(&api_def, vars)?;
+    if !api_def.root.is_dir() {
+        diagnostics.push(
+            cloud_toml
+                .diagnostic(
+                    Some(raw.root.span()),
+                    ApiDefinitionError::MissingRoot(api_def.root.display().to_string()),
+                )
+                .with_key("root"),
+        );
 
-    Ok(api_def)
+        return api_def;
+    }
+
+    api_def.endpoints = create_api_definitions(&api_def, vars, diagnostics);
+
+    api_def
 }
 
 pub fn load_api_configs(
+    base_path: &Path,
+    cloud_toml: &SourceFile,
     api_defs: impl Iterator<Item = ApiConfigRaw>,
     vars: &ConfigVariables,
-) -> Result<Vec<ApiConfig>, ApiDefinitionError> {
+    diagnostics: &mut Diagnostics,
+) -> Vec<ApiConfig> {
     api_defs
-        .map(|raw| load_raw_api_config(raw, vars))
-        .collect::<Result<Vec<_>, ApiDefinitionError>>()
+        .map(|raw| load_raw_api_config(base_path, cloud_toml, raw, vars, diagnostics))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::config::cloud_config::{CloudConfigRaw, CloudOverlayRaw};
+
+    fn source(path: &str, contents: &str) -> SourceFile {
+        SourceFile {
+            path: PathBuf::from(path),
+            contents: contents.to_owned(),
+        }
+    }
+
+    /// Loads the `[[api]]`s in `cloud_toml` with `overlay` applied, returning the diagnostics
+    fn load(cloud_toml: &str, overlay: &str) -> (Vec<ApiConfig>, Diagnostics) {
+        let mut diagnostics = Diagnostics::new();
+        let cloud_toml = source("cloud.toml", cloud_toml);
+        let overlay_toml = source("cloud.staging.toml", overlay);
+
+        let mut apis = cloud_toml
+            .parse::<CloudConfigRaw>(&mut diagnostics)
+            .and_then(|x| x.api)
+            .unwrap_or_default();
+        let overlays = overlay_toml
+            .parse::<CloudOverlayRaw>(&mut diagnostics)
+            .and_then(|x| x.api)
+            .unwrap_or_default();
+        apply_api_overlays(&mut apis, overlays, &overlay_toml, &mut diagnostics);
+
+        let apis = load_api_configs(
+            Path::new("."),
+            &cloud_toml,
+            apis.into_iter(),
+            &ConfigVariables::new(),
+            &mut diagnostics,
+        );
+
+        (apis, diagnostics)
+    }
+
+    fn endpoint_type_problems(diagnostics: &Diagnostics) -> Vec<String> {
+        diagnostics
+            .iter()
+            .filter(|x| x.key.as_deref() == Some("endpoint_type"))
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    const CLOUD_TOML: &str = "[[api]]\nname = \"main\"\nroot = \"api\"\nprefix = \"api\"\n";
+
+    #[test]
+    fn overrides_fields() {
+        let (apis, _) = load(
+            CLOUD_TOML,
+            "[[api]]\nname = \"main\"\nprefix = \"v2\"\nendpoint_type = \"edge\"\n",
+        );
+
+        assert_eq!(apis[0].prefix, Route::parse("v2"));
+        assert_eq!(apis[0].endpoint_type, EndpointType::Edge);
+    }
+
+    #[test]
+    fn keeps_fields_the_overlay_doesnt_set() {
+        let (apis, _) = load(
+            CLOUD_TOML,
+            "[[api]]\nname = \"main\"\ndomain = \"staging.example.com\"\n",
+        );
+
+        assert_eq!(apis[0].domain.as_deref(), Some("staging.example.com"));
+        assert_eq!(apis[0].prefix, Route::parse("api"));
+        assert_eq!(apis[0].endpoint_type, EndpointType::Regional);
+    }
+
+    #[test]
+    fn only_overrides_the_named_api() {
+        let (apis, _) = load(
+            &format!("{CLOUD_TOML}[[api]]\nname = \"admin\"\nroot = \"admin\"\n"),
+            "[[api]]\nname = \"admin\"\nprefix = \"v2\"\n",
+        );
+
+        assert_eq!(apis[0].prefix, Route::parse("api"));
+        assert_eq!(apis[1].prefix, Route::parse("v2"));
+    }
+
+    #[test]
+    fn reports_overlays_for_missing_apis() {
+        let (_, diagnostics) = load(CLOUD_TOML, "[[api]]\nname = \"other\"\n");
+
+        let problem = diagnostics
+            .iter()
+            .find(|x| x.file == Path::new("cloud.staging.toml"))
+            .unwrap();
+        assert_eq!(
+            problem.to_string(),
+            "cloud.staging.toml:2:8: No [[api]] named other in cloud.toml (at `name`)"
+        );
+    }
+
+    #[test]
+    fn reports_invalid_overlay_values_in_the_overlay() {
+        let (_, diagnostics) = load(
+            CLOUD_TOML,
+            "[[api]]\nname = \"main\"\nendpoint_type = \"bogus\"\n",
+        );
+
+        assert_eq!(
+            endpoint_type_problems(&diagnostics),
+            vec!["cloud.staging.toml:3:17: Invalid endpoint type: bogus (expected regional, edge or private) (at `endpoint_type`)"]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_values_in_cloud_toml() {
+        let (_, diagnostics) = load(
+            "[[api]]\nname = \"main\"\nroot = \"api\"\nendpoint_type = \"bogus\"\n",
+            "",
+        );
+
+        assert_eq!(
+            endpoint_type_problems(&diagnostics),
+            vec!["cloud.toml:4:17: Invalid endpoint type: bogus (expected regional, edge or private) (at `endpoint_type`)"]
+        );
+    }
 }
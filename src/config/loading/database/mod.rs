@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use toml::Spanned;
+
+use crate::config::{
+    database::database_config::DatabaseConfig, ConfigVariableError, ConfigVariables,
+    ContainsVariables,
+};
+
+use super::diagnostics::{Diagnostics, SourceFile};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseConfigRaw {
+    pub name: Spanned<String>,
+    pub engine: String,
+}
+
+impl ContainsVariables for DatabaseConfigRaw {
+    fn replace_variables(&mut self, vars: &ConfigVariables, errors: &mut Vec<ConfigVariableError>) {
+        self.name.replace_variables(vars, errors);
+        self.engine.replace_variables(vars, errors);
+    }
+}
+
+pub fn load_database_configs(
+    cloud_toml: &SourceFile,
+    database_defs: impl Iterator<Item = DatabaseConfigRaw>,
+    vars: &ConfigVariables,
+    diagnostics: &mut Diagnostics,
+) -> Vec<DatabaseConfig> {
+    database_defs
+        .map(|mut raw| {
+            let mut errors = vec![];
+            raw.replace_variables(vars, &mut errors);
+            for e in errors {
+                diagnostics.push(cloud_toml.diagnostic(Some(raw.name.span()), e));
+            }
+
+            DatabaseConfig {
+                source: cloud_toml.source_ref(Some(raw.name.span())),
+                name: raw.name.into_inner(),
+                engine: raw.engine,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,314 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::cloud::api::ApiDefinitionError;
+
+/// A 1-based line + column within a config file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceLocation {
+    pub fn from_offset(contents: &str, offset: usize) -> Self {
+        let before = &contents[..offset.min(contents.len())];
+
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+        let column = before[line_start..].chars().count() + 1;
+
+        Self { line, column }
+    }
+}
+
+/// Where something was declared in the config, so later problems with it can point back there
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SourceRef {
+    pub file: PathBuf,
+    pub location: Option<SourceLocation>,
+}
+
+/// A single problem found while loading the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub location: Option<SourceLocation>,
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(file: impl Into<PathBuf>, message: impl Display) -> Self {
+        Self {
+            file: file.into(),
+            location: None,
+            key: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// Creates a diagnostic pointing at where something was declared
+    pub fn at(source: &SourceRef, message: impl Display) -> Self {
+        Self {
+            location: source.location,
+            ..Self::new(&source.file, message)
+        }
+    }
+
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(location) = self.location {
+            write!(f, ":{}:{}", location.line, location.column)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(key) = &self.key {
+            write!(f, " (at `{key}`)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Every problem found while loading the config.
+///
+/// Loading keeps going after a problem is found, so all mistakes can be reported at once.
+#[derive(Error, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Diagnostics(Vec<Diagnostic>);
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Found {} problem(s) in the config:", self.0.len())?;
+        for diagnostic in self.0.iter() {
+            write!(f, "\n  {diagnostic}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a problem, ignoring it if it has already been reported (e.g. two APIs sharing a root)
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        if !self.0.contains(&diagnostic) {
+            self.0.push(diagnostic);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter()
+    }
+
+    /// Returns `Ok(value)` if no problems were found
+    pub fn into_result<T>(self, value: T) -> Result<T, Self> {
+        if self.is_empty() {
+            Ok(value)
+        } else {
+            Err(self)
+        }
+    }
+}
+
+/// A config file's contents, kept around so spans can be turned into line + column numbers
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+impl SourceFile {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_owned(),
+            contents: fs::read_to_string(path)?,
+        })
+    }
+
+    /// Reads the file, reporting a diagnostic if it cannot be read
+    pub fn load(path: &Path, diagnostics: &mut Diagnostics) -> Option<Self> {
+        match Self::read(path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                diagnostics.push(Diagnostic::new(
+                    path,
+                    ApiDefinitionError::CannotReadTomlFile(e),
+                ));
+                None
+            }
+        }
+    }
+
+    /// Parses the file as TOML, reporting a diagnostic if it is invalid
+    pub fn parse<T: DeserializeOwned>(&self, diagnostics: &mut Diagnostics) -> Option<T> {
+        match toml::from_str::<T>(&self.contents) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                let message = e.message().lines().collect::<Vec<_>>().join(", ");
+
+                let mut diagnostic = self.diagnostic(
+                    e.span(),
+                    ApiDefinitionError::CannotParseTomlFile(message.clone()),
+                );
+                diagnostic.key = missing_field(&message)
+                    .or_else(|| e.span().and_then(|span| self.key_at(span.start)));
+
+                diagnostics.push(diagnostic);
+                None
+            }
+        }
+    }
+
+    /// Creates a diagnostic pointing at this span of the file
+    pub fn diagnostic(&self, span: Option<Range<usize>>, message: impl Display) -> Diagnostic {
+        Diagnostic::at(&self.source_ref(span), message)
+    }
+
+    /// Refers to this span of the file
+    pub fn source_ref(&self, span: Option<Range<usize>>) -> SourceRef {
+        SourceRef {
+            file: self.path.clone(),
+            location: span.map(|x| SourceLocation::from_offset(&self.contents, x.start)),
+        }
+    }
+
+    /// Finds the key being assigned on the line containing this offset, if there is one
+    fn key_at(&self, offset: usize) -> Option<String> {
+        let line_start = self.contents[..offset.min(self.contents.len())]
+            .rfind('\n')
+            .map(|x| x + 1)
+            .unwrap_or(0);
+        let line = self.contents[line_start..].lines().next()?;
+
+        let (key, _) = line.split_once('=')?;
+        let key = key.trim();
+
+        (!key.is_empty() && !key.starts_with('[')).then(|| key.to_owned())
+    }
+}
+
+/// Gets the field name out of serde's "missing field `name`" message
+fn missing_field(message: &str) -> Option<String> {
+    let field = message.strip_prefix("missing field `")?;
+
+    field.split_once('`').map(|(field, _)| field.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(contents: &str) -> SourceFile {
+        SourceFile {
+            path: PathBuf::from("cloud.toml"),
+            contents: contents.into(),
+        }
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Api {
+        name: String,
+        port: u16,
+    }
+
+    #[test]
+    fn finds_lines_and_columns() {
+        let contents = "a = 1\nbé = 2\n";
+
+        assert_eq!(
+            SourceLocation::from_offset(contents, 0),
+            SourceLocation { line: 1, column: 1 }
+        );
+        assert_eq!(
+            SourceLocation::from_offset(contents, 6),
+            SourceLocation { line: 2, column: 1 }
+        );
+        // Columns count characters, not bytes
+        assert_eq!(
+            SourceLocation::from_offset(contents, 10),
+            SourceLocation { line: 2, column: 4 }
+        );
+        assert_eq!(
+            SourceLocation::from_offset(contents, 100),
+            SourceLocation { line: 3, column: 1 }
+        );
+    }
+
+    #[test]
+    fn displays_where_problems_are() {
+        assert_eq!(
+            Diagnostic::new("cloud.toml", "Bad").to_string(),
+            "cloud.toml: Bad"
+        );
+        assert_eq!(
+            source("a = 1\nb = 2\n")
+                .diagnostic(Some(6..7), "Bad")
+                .with_key("b")
+                .to_string(),
+            "cloud.toml:2:1: Bad (at `b`)"
+        );
+    }
+
+    #[test]
+    fn reports_every_problem_once() {
+        let mut diagnostics = Diagnostics::new();
+        assert_eq!(diagnostics.clone().into_result(1), Ok(1));
+
+        diagnostics.push(Diagnostic::new("a.toml", "First"));
+        diagnostics.push(Diagnostic::new("b.toml", "Second"));
+        diagnostics.push(Diagnostic::new("a.toml", "First"));
+
+        assert_eq!(diagnostics.iter().count(), 2);
+        assert_eq!(
+            diagnostics.clone().into_result(1).unwrap_err().to_string(),
+            "Found 2 problem(s) in the config:\n  a.toml: First\n  b.toml: Second"
+        );
+    }
+
+    #[test]
+    fn points_parse_errors_at_the_key() {
+        let mut diagnostics = Diagnostics::new();
+        let parsed = source("name = \"main\"\nport = \"80\"\n").parse::<Api>(&mut diagnostics);
+
+        assert!(parsed.is_none());
+        let diagnostic = diagnostics.iter().next().unwrap();
+        assert_eq!(
+            diagnostic.location,
+            Some(SourceLocation { line: 2, column: 8 })
+        );
+        assert_eq!(diagnostic.key.as_deref(), Some("port"));
+    }
+
+    #[test]
+    fn names_missing_fields() {
+        let mut diagnostics = Diagnostics::new();
+        source("name = \"main\"\n").parse::<Api>(&mut diagnostics);
+
+        let diagnostic = diagnostics.iter().next().unwrap();
+        assert_eq!(diagnostic.key.as_deref(), Some("port"));
+        assert_eq!(missing_field("missing field `name`"), Some("name".into()));
+        assert_eq!(missing_field("invalid type"), None);
+    }
+}
@@ -1,32 +1,244 @@
-use std::fs;
+use std::path::Path;
 
-use api::load_api_configs;
-use walkdir::WalkDir;
+use api::{apply_api_overlays, load_api_configs};
+use database::load_database_configs;
+use diagnostics::{Diagnostic, Diagnostics, SourceFile};
+use variables::load_variables;
 
 use super::{
-    cloud_config::{CloudConfig, CloudConfigRaw},
-    ConfigVariable, ConfigVariables,
+    cloud_config::{CloudConfig, CloudConfigRaw, CloudOverlayRaw},
+    validation::validate_config,
+    ConfigVariable, ContainsVariables,
 };
 
 pub mod api;
+pub mod database;
+pub mod diagnostics;
+pub mod variables;
 
-pub fn load_configs(base_path: &str) -> anyhow::Result<CloudConfig> {
-    let base_path = "samples/testing";
+/// Settings that change how a project is loaded, usually given on the command line
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Variables that take priority over every other variable source
+    pub vars: Vec<(String, String)>,
+    /// The environment to load, which applies `cloud.<environment>.toml` on top of `cloud.toml`
+    pub environment: Option<String>,
+}
+
+/// Loads the `cloud.toml` in this directory + every api endpoint it references, then validates the
+/// result.
+///
+/// All problems found are returned together rather than stopping at the first one.
+pub fn load_configs(base_path: &Path, options: &LoadOptions) -> Result<CloudConfig, Diagnostics> {
+    let mut diagnostics = Diagnostics::new();
+
+    let Some(cloud_toml) = SourceFile::load(&base_path.join("cloud.toml"), &mut diagnostics) else {
+        return Err(diagnostics);
+    };
+    let Some(cloud_config_raw) = cloud_toml.parse::<CloudConfigRaw>(&mut diagnostics) else {
+        return Err(diagnostics);
+    };
+    let project_name = cloud_config_raw
+        .project
+        .and_then(|x| x.name)
+        .or_else(|| {
+            let path = base_path.canonicalize().ok()?;
+            Some(path.file_name()?.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "instacloud".into());
+
+    let mut api_raws = cloud_config_raw.api.unwrap_or_default();
+    let mut terraform_raw = cloud_config_raw.terraform.map(|x| (cloud_toml.clone(), x));
+
+    let mut overlay_vars = None;
+    if let Some(environment) = &options.environment {
+        // Environment names end up in file + resource names, so hold them to the same rules as variables
+        if ConfigVariable::new(&environment.replace('-', "_")).is_err() {
+            diagnostics.push(Diagnostic::new(
+                "--env",
+                format!("Invalid environment name - {environment}"),
+            ));
+            return Err(diagnostics);
+        }
 
-    let cloud_toml = fs::read_to_string(format!("{base_path}/cloud.toml"))?;
+        let overlay_path = base_path.join(format!("cloud.{environment}.toml"));
+        if overlay_path.exists() {
+            if let Some(overlay_toml) = SourceFile::load(&overlay_path, &mut diagnostics) {
+                if let Some(overlay) = overlay_toml.parse::<CloudOverlayRaw>(&mut diagnostics) {
+                    apply_api_overlays(
+                        &mut api_raws,
+                        overlay.api.unwrap_or_default(),
+                        &overlay_toml,
+                        &mut diagnostics,
+                    );
 
-    let cloud_config_raw =
-        toml::from_str::<CloudConfigRaw>(&cloud_toml).expect("Failed to parse cloud.toml");
+                    if let Some(terraform) = overlay.terraform {
+                        terraform_raw = Some((overlay_toml.clone(), terraform));
+                    }
+                    overlay_vars = overlay.vars.map(|vars| (overlay_toml, vars));
+                }
+            }
+        }
+    }
+
+    let vars = load_variables(
+        base_path,
+        overlay_vars.as_ref().map(|(source, vars)| (source, vars)),
+        &options.vars,
+        &mut diagnostics,
+    );
+
+    let api_configs = load_api_configs(
+        base_path,
+        &cloud_toml,
+        api_raws.into_iter(),
+        &vars,
+        &mut diagnostics,
+    );
 
-    // TODO: load from config file/env file
-    let mut vars = ConfigVariables::new();
-    vars.insert(
-        ConfigVariable::new("domain").unwrap(),
-        "api.cornchipss.com".to_owned(),
+    let database_configs = load_database_configs(
+        &cloud_toml,
+        cloud_config_raw.database.unwrap_or_default().into_iter(),
+        &vars,
+        &mut diagnostics,
     );
 
-    let api_configs =
-        load_api_configs(cloud_config_raw.api.unwrap_or_default().into_iter(), &vars)?;
+    let terraform = match terraform_raw {
+        Some((source, mut terraform)) => {
+            let mut errors = vec![];
+            terraform.replace_variables(&vars, &mut errors);
+            for e in errors {
+                diagnostics.push(source.diagnostic(None, e).with_key("terraform"));
+            }
+            terraform
+        }
+        None => Default::default(),
+    };
+
+    let config = CloudConfig {
+        project_name,
+        environment: options.environment.clone(),
+        api: api_configs,
+        database: database_configs,
+        outputs: cloud_config_raw.outputs.unwrap_or_default(),
+        terraform,
+    };
+
+    validate_config(&config, &mut diagnostics);
+
+    diagnostics.into_result(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use super::*;
+
+    /// A project directory with these files in it
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("instacloud-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("api")).unwrap();
+
+        for (path, contents) in files {
+            fs::write(dir.join(path), contents).unwrap();
+        }
+
+        dir
+    }
+
+    fn load(dir: &Path, environment: Option<&str>, vars: &[(&str, &str)]) -> CloudConfig {
+        let options = LoadOptions {
+            vars: vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            environment: environment.map(str::to_owned),
+        };
+
+        load_configs(dir, &options).unwrap()
+    }
+
+    const CLOUD_TOML: &str =
+        "[[api]]\nname = \"main\"\nroot = \"api\"\ndomain = \"${domain}\"\nprefix = \"api\"\n";
+
+    #[test]
+    fn rejects_invalid_environment_names() {
+        let dir = project("env-names", &[("cloud.toml", CLOUD_TOML)]);
+        let options = LoadOptions {
+            environment: Some("../prod".into()),
+            ..Default::default()
+        };
+
+        let diagnostics = load_configs(&dir, &options).unwrap_err();
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["--env: Invalid environment name - ../prod"]
+        );
+
+        let options = LoadOptions {
+            environment: Some("staging-eu".into()),
+            vars: vec![("domain".into(), "example.com".into())],
+        };
+        assert!(load_configs(&dir, &options).is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn applies_the_environment_overlay() {
+        let dir = project(
+            "overlay",
+            &[
+                ("cloud.toml", CLOUD_TOML),
+                ("vars.toml", "domain = \"vars.example.com\"\n"),
+                ("cloud.staging.toml", "[vars]\ndomain = \"staging.example.com\"\n\n[[api]]\nname = \"main\"\nprefix = \"v2\"\n"),
+            ],
+        );
+
+        let config = load(&dir, None, &[]);
+        assert_eq!(config.api[0].domain.as_deref(), Some("vars.example.com"));
+        assert_eq!(config.api[0].prefix.to_string(), "/api");
+
+        let config = load(&dir, Some("staging"), &[]);
+        assert_eq!(config.environment.as_deref(), Some("staging"));
+        assert_eq!(config.api[0].domain.as_deref(), Some("staging.example.com"));
+        assert_eq!(config.api[0].prefix.to_string(), "/v2");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn layers_variables() {
+        let dir = project(
+            "vars",
+            &[
+                ("cloud.toml", CLOUD_TOML),
+                ("vars.toml", "domain = \"vars.example.com\"\n"),
+                (".env", "domain=env.example.com\n"),
+                (
+                    "cloud.staging.toml",
+                    "[vars]\ndomain = \"staging.example.com\"\n",
+                ),
+            ],
+        );
+
+        // `.env` beats `vars.toml`
+        let config = load(&dir, None, &[]);
+        assert_eq!(config.api[0].domain.as_deref(), Some("env.example.com"));
+
+        // An environment's vars beat a developer's `.env`
+        let config = load(&dir, Some("staging"), &[]);
+        assert_eq!(config.api[0].domain.as_deref(), Some("staging.example.com"));
+
+        // `--var` beats everything
+        let config = load(&dir, Some("staging"), &[("domain", "cli.example.com")]);
+        assert_eq!(config.api[0].domain.as_deref(), Some("cli.example.com"));
 
-    Ok(CloudConfig { api: api_configs })
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
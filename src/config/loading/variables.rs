@@ -0,0 +1,212 @@
+use std::{collections::BTreeMap, env, path::Path};
+
+use crate::config::{ConfigVariable, ConfigVariables};
+
+use super::diagnostics::{Diagnostic, Diagnostics, SourceFile};
+
+/// Environment variables starting with this are made available as config variables (with the
+/// prefix removed), e.g. `INSTACLOUD_VAR_domain=example.com` defines `${domain}`.
+pub const ENV_VAR_PREFIX: &str = "INSTACLOUD_VAR_";
+
+/// Collects the config variables for a project.
+///
+/// Later sources override earlier ones:
+/// 1. `vars.toml` in the project root
+/// 2. `.env` in the project root
+/// 3. The `[vars]` table of the selected environment's `cloud.<env>.toml`, so a developer's `.env`
+///    can't change what an environment is deployed with
+/// 4. Process environment variables prefixed with [`ENV_VAR_PREFIX`]
+/// 5. `overrides` (from `--var key=value` on the command line)
+pub fn load_variables(
+    base_path: &Path,
+    environment_vars: Option<(&SourceFile, &BTreeMap<String, toml::Value>)>,
+    overrides: &[(String, String)],
+    diagnostics: &mut Diagnostics,
+) -> ConfigVariables {
+    let mut vars = ConfigVariables::new();
+
+    let vars_toml = base_path.join("vars.toml");
+    if vars_toml.exists() {
+        if let Some(source) = SourceFile::load(&vars_toml, diagnostics) {
+            if let Some(table) = source.parse::<BTreeMap<String, toml::Value>>(diagnostics) {
+                insert_table(&mut vars, &source, &table, diagnostics);
+            }
+        }
+    }
+
+    let dot_env = base_path.join(".env");
+    if dot_env.exists() {
+        if let Some(source) = SourceFile::load(&dot_env, diagnostics) {
+            for (name, value) in parse_dot_env(&source, diagnostics) {
+                insert(&mut vars, &source, &name, value, diagnostics);
+            }
+        }
+    }
+
+    if let Some((source, table)) = environment_vars {
+        insert_table(&mut vars, source, table, diagnostics);
+    }
+
+    for (name, value) in env::vars() {
+        let Some(name) = name.strip_prefix(ENV_VAR_PREFIX) else {
+            continue;
+        };
+
+        if let Ok(var) = ConfigVariable::new(name) {
+            vars.insert(var, value);
+        }
+    }
+
+    for (name, value) in overrides {
+        match ConfigVariable::new(name) {
+            Ok(var) => vars.insert(var, value.clone()),
+            Err(e) => diagnostics.push(Diagnostic::new("--var", e)),
+        }
+    }
+
+    vars
+}
+
+fn insert_table(
+    vars: &mut ConfigVariables,
+    source: &SourceFile,
+    table: &BTreeMap<String, toml::Value>,
+    diagnostics: &mut Diagnostics,
+) {
+    for (name, value) in table {
+        let value = match value {
+            toml::Value::String(s) => s.clone(),
+            toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                value.to_string()
+            }
+            _ => {
+                diagnostics.push(
+                    source
+                        .diagnostic(None, "Variables must be strings, numbers or booleans")
+                        .with_key(name),
+                );
+                continue;
+            }
+        };
+
+        insert(vars, source, name, value, diagnostics);
+    }
+}
+
+fn insert(
+    vars: &mut ConfigVariables,
+    source: &SourceFile,
+    name: &str,
+    value: String,
+    diagnostics: &mut Diagnostics,
+) {
+    match ConfigVariable::new(name) {
+        Ok(var) => vars.insert(var, value),
+        Err(e) => diagnostics.push(source.diagnostic(None, e).with_key(name)),
+    }
+}
+
+/// Parses `KEY=VALUE` lines, ignoring blank lines + `#` comments.
+///
+/// Values may optionally be wrapped in single or double quotes, and lines may start with `export`.
+fn parse_dot_env(source: &SourceFile, diagnostics: &mut Diagnostics) -> Vec<(String, String)> {
+    let mut result = vec![];
+
+    let mut offset = 0;
+    for line in source.contents.split_inclusive('\n') {
+        let line_offset = offset;
+        offset += line.len();
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let Some((name, value)) = line.split_once('=') else {
+            diagnostics.push(source.diagnostic(
+                Some(line_offset..line_offset),
+                "Expected a line of the form KEY=VALUE",
+            ));
+            continue;
+        };
+
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|q| value.strip_prefix(*q).and_then(|x| x.strip_suffix(*q)))
+            .unwrap_or(value);
+
+        result.push((name.trim().to_owned(), value.to_owned()));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn parse(contents: &str) -> (Vec<(String, String)>, Diagnostics) {
+        let source = SourceFile {
+            path: PathBuf::from(".env"),
+            contents: contents.to_owned(),
+        };
+        let mut diagnostics = Diagnostics::new();
+        let vars = parse_dot_env(&source, &mut diagnostics);
+
+        (vars, diagnostics)
+    }
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_lines() {
+        let (vars, diagnostics) =
+            parse("# comment\n\ndomain=example.com\n  export region = us-east-1  \r\nempty=\n");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(
+            vars,
+            pairs(&[
+                ("domain", "example.com"),
+                ("region", "us-east-1"),
+                ("empty", ""),
+            ])
+        );
+    }
+
+    #[test]
+    fn strips_matching_quotes() {
+        let (vars, _) = parse("a=\"quoted value\"\nb='single'\nc=\"mismatched'\nd=x=y\n");
+
+        assert_eq!(
+            vars,
+            pairs(&[
+                ("a", "quoted value"),
+                ("b", "single"),
+                ("c", "\"mismatched'"),
+                ("d", "x=y"),
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_lines_without_a_value() {
+        let (vars, diagnostics) = parse("a=1\nnot a variable\nb=2");
+
+        assert_eq!(vars, pairs(&[("a", "1"), ("b", "2")]));
+
+        let diagnostics = diagnostics.iter().collect::<Vec<_>>();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].location.map(|x| x.line), Some(2));
+    }
+}
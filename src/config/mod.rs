@@ -2,39 +2,116 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use toml::Spanned;
 
 pub mod api;
 pub mod cloud_config;
-mod loading;
+pub mod database;
+pub mod loading;
+pub mod outputs;
+pub mod terraform;
+pub mod validation;
 
 pub trait ContainsVariables {
-    fn replace_variables(&mut self, vars: &ConfigVariables);
+    /// Substitutes every variable reference, recording any references that cannot be resolved
+    fn replace_variables(&mut self, vars: &ConfigVariables, errors: &mut Vec<ConfigVariableError>);
 }
 
 impl ContainsVariables for String {
-    fn replace_variables(&mut self, vars: &ConfigVariables) {
-        for (k, v) in vars.0.iter() {
-            *self = self.replace(&k.0, v);
+    fn replace_variables(&mut self, vars: &ConfigVariables, errors: &mut Vec<ConfigVariableError>) {
+        match substitute_variables(self, vars) {
+            Ok(s) => *self = s,
+            Err(mut e) => errors.append(&mut e),
         }
     }
 }
 
+/// Expands a template string.
+///
+/// - `${name}` is replaced with the variable's value
+/// - `${name:-default}` is replaced with the variable's value, or `default` if it isn't defined
+/// - `$$` is a literal `$`, in `default` as well as the rest of the template
+///
+/// Every problem in the template is returned, not just the first.
+pub fn substitute_variables(
+    template: &str,
+    vars: &ConfigVariables,
+) -> Result<String, Vec<ConfigVariableError>> {
+    let mut result = String::with_capacity(template.len());
+    let mut errors = vec![];
+
+    let mut rest = template;
+    while let Some(idx) = rest.find('$') {
+        result.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+            continue;
+        }
+
+        let Some(after) = rest.strip_prefix('{') else {
+            errors.push(ConfigVariableError::InvalidReference(template.to_owned()));
+            continue;
+        };
+
+        let Some(end) = after.find('}') else {
+            errors.push(ConfigVariableError::Unterminated(template.to_owned()));
+            rest = "";
+            break;
+        };
+
+        let reference = &after[..end];
+        rest = &after[end + 1..];
+
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+
+        if let Err(e) = ConfigVariable::new(name) {
+            errors.push(e);
+            continue;
+        }
+
+        match (vars.get(name), default) {
+            (Some(value), _) => result.push_str(value),
+            (None, Some(default)) => result.push_str(&default.replace("$$", "$")),
+            (None, None) => errors.push(ConfigVariableError::Undefined(name.to_owned())),
+        }
+    }
+    result.push_str(rest);
+
+    if errors.is_empty() {
+        Ok(result)
+    } else {
+        Err(errors)
+    }
+}
+
 impl<T: ContainsVariables + Sized> ContainsVariables for Vec<T> {
-    fn replace_variables(&mut self, vars: &ConfigVariables) {
+    fn replace_variables(&mut self, vars: &ConfigVariables, errors: &mut Vec<ConfigVariableError>) {
         for item in self {
-            item.replace_variables(vars);
+            item.replace_variables(vars, errors);
         }
     }
 }
 
 impl<T: ContainsVariables + Sized> ContainsVariables for Option<T> {
-    fn replace_variables(&mut self, vars: &ConfigVariables) {
+    fn replace_variables(&mut self, vars: &ConfigVariables, errors: &mut Vec<ConfigVariableError>) {
         if let Some(s) = self {
-            s.replace_variables(vars);
+            s.replace_variables(vars, errors);
         }
     }
 }
 
+impl<T: ContainsVariables> ContainsVariables for Spanned<T> {
+    fn replace_variables(&mut self, vars: &ConfigVariables, errors: &mut Vec<ConfigVariableError>) {
+        self.get_mut().replace_variables(vars, errors);
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ConfigVariable(String);
 
@@ -42,15 +119,26 @@ pub struct ConfigVariable(String);
 pub enum ConfigVariableError {
     #[error("Invalid variable name - {0}")]
     InvalidName(String),
+    #[error("Undefined variable - {0}")]
+    Undefined(String),
+    #[error("Unterminated variable reference (missing `}}`) in \"{0}\"")]
+    Unterminated(String),
+    #[error("`$` must be followed by `{{name}}` or another `$` in \"{0}\"")]
+    InvalidReference(String),
 }
 
 impl ConfigVariable {
     pub fn new(var_name: &str) -> Result<Self, ConfigVariableError> {
-        if var_name.split_whitespace().count() != 1 {
+        if var_name.is_empty() || !var_name.chars().all(Self::is_name_char) {
             return Err(ConfigVariableError::InvalidName(var_name.to_owned()));
         }
 
-        Ok(Self(format!("${var_name}")))
+        Ok(Self(var_name.to_owned()))
+    }
+
+    /// Variable names may only contain letters, numbers, and underscores
+    pub fn is_name_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '_'
     }
 }
 
@@ -66,7 +154,85 @@ impl ConfigVariables {
         self.0.insert(variable, value);
     }
 
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a ConfigVariable, &'a String)> {
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.0.get(&ConfigVariable(name.to_owned()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ConfigVariable, &String)> {
         self.0.iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(items: &[(&str, &str)]) -> ConfigVariables {
+        let mut vars = ConfigVariables::new();
+        for (name, value) in items {
+            vars.insert(ConfigVariable::new(name).unwrap(), value.to_string());
+        }
+        vars
+    }
+
+    fn substitute(template: &str) -> Result<String, Vec<ConfigVariableError>> {
+        substitute_variables(template, &vars(&[("a", "1"), ("a_b", "2"), ("empty", "")]))
+    }
+
+    #[test]
+    fn replaces_whole_names() {
+        assert_eq!(substitute("${a}").unwrap(), "1");
+        assert_eq!(substitute("${a_b}").unwrap(), "2");
+        assert_eq!(substitute("x${a}${a_b}y").unwrap(), "x12y");
+        assert_eq!(substitute("no variables").unwrap(), "no variables");
+    }
+
+    #[test]
+    fn falls_back_to_defaults() {
+        assert_eq!(substitute("${x:-d}").unwrap(), "d");
+        assert_eq!(substitute("${a:-d}").unwrap(), "1");
+        assert_eq!(substitute("${x:-}").unwrap(), "");
+        assert_eq!(substitute("${x:-a:-b}").unwrap(), "a:-b");
+        assert_eq!(substitute("${x:-a$$b}").unwrap(), "a$b");
+        assert_eq!(substitute("${x:-$$$$}").unwrap(), "$$");
+        // Defined but empty still counts as defined
+        assert_eq!(substitute("${empty:-d}").unwrap(), "");
+    }
+
+    #[test]
+    fn escapes_dollars() {
+        assert_eq!(substitute("$$").unwrap(), "$");
+        assert_eq!(substitute("$${a}").unwrap(), "${a}");
+        assert_eq!(substitute("$$$${a}").unwrap(), "$${a}");
+        assert_eq!(substitute("cost: $$${a}").unwrap(), "cost: $1");
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let errors = substitute("${x} ${y} ${a}").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], ConfigVariableError::Undefined(x) if x == "x"));
+        assert!(matches!(&errors[1], ConfigVariableError::Undefined(y) if y == "y"));
+
+        let errors = substitute("$a ${bad name} ${a").unwrap_err();
+        assert!(matches!(
+            errors[0],
+            ConfigVariableError::InvalidReference(_)
+        ));
+        assert!(matches!(&errors[1], ConfigVariableError::InvalidName(x) if x == "bad name"));
+        assert!(matches!(errors[2], ConfigVariableError::Unterminated(_)));
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn reports_unterminated_references() {
+        let errors = substitute("${a").unwrap_err();
+        assert!(matches!(errors[..], [ConfigVariableError::Unterminated(_)]));
+
+        let errors = substitute("trailing $").unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [ConfigVariableError::InvalidReference(_)]
+        ));
+    }
+}
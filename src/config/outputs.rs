@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// The `[outputs]` table of `cloud.toml`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputsConfig {
+    /// Names of generated outputs (e.g. `role_lambda_role_arn`) that terraform should hide in its
+    /// plan + apply output
+    #[serde(default)]
+    pub sensitive: Vec<String>,
+}
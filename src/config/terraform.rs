@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use super::{ConfigVariableError, ConfigVariables, ContainsVariables};
+
+/// The `[terraform]` table of `cloud.toml`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TerraformConfig {
+    /// Terraform CLI versions the generated terraform may be used with
+    #[serde(default = "default_required_version")]
+    pub required_version: String,
+    /// Versions of the `hashicorp/aws` provider the generated terraform may be used with
+    #[serde(default = "default_aws_provider_version")]
+    pub aws_provider_version: String,
+    /// Where terraform keeps its state. Terraform's default (a local `terraform.tfstate`) is used
+    /// if this isn't set.
+    pub backend: Option<BackendConfig>,
+}
+
+fn default_required_version() -> String {
+    ">= 1.5.0".into()
+}
+
+fn default_aws_provider_version() -> String {
+    "~> 5.0".into()
+}
+
+impl Default for TerraformConfig {
+    fn default() -> Self {
+        Self {
+            required_version: default_required_version(),
+            aws_provider_version: default_aws_provider_version(),
+            backend: None,
+        }
+    }
+}
+
+/// `[terraform.backend]`, e.g. `type = "s3"` + `bucket = "my-state"`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BackendConfig {
+    Local {
+        /// Path of the state file, relative to the generated terraform
+        path: Option<String>,
+    },
+    S3 {
+        /// Defaults to the bucket made by `instacloud bootstrap`, along with `dynamodb_table`
+        bucket: Option<String>,
+        /// Path of the state file in the bucket. Defaults to `terraform.tfstate`, or
+        /// `<env>/terraform.tfstate` when an environment is selected.
+        key: Option<String>,
+        /// Defaults to the region the stack is deployed to
+        region: Option<String>,
+        /// DynamoDB table used to lock the state while terraform is running
+        dynamodb_table: Option<String>,
+    },
+}
+
+impl ContainsVariables for TerraformConfig {
+    fn replace_variables(&mut self, vars: &ConfigVariables, errors: &mut Vec<ConfigVariableError>) {
+        self.required_version.replace_variables(vars, errors);
+        self.aws_provider_version.replace_variables(vars, errors);
+
+        match &mut self.backend {
+            Some(BackendConfig::Local { path }) => path.replace_variables(vars, errors),
+            Some(BackendConfig::S3 {
+                bucket,
+                key,
+                region,
+                dynamodb_table,
+            }) => {
+                bucket.replace_variables(vars, errors);
+                key.replace_variables(vars, errors);
+                region.replace_variables(vars, errors);
+                dynamodb_table.replace_variables(vars, errors);
+            }
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,594 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::cloud::api::{ApiDefinitionError, EndpointType, PathSegment, Route};
+
+use super::{
+    api::api_config::{ApiConfig, ApiEndpoint},
+    cloud_config::{CloudConfig, NameUniquenessChecker},
+    loading::diagnostics::{Diagnostic, Diagnostics, SourceRef},
+};
+
+/// Checks the loaded config for problems that can't be caught while parsing it, such as duplicate
+/// names or references to resources that don't exist.
+pub fn validate_config(config: &CloudConfig, diagnostics: &mut Diagnostics) {
+    if !config
+        .api
+        .iter()
+        .map(|x| x.name.as_str())
+        .check_all_unique_names()
+    {
+        report_duplicate_names(
+            config.api.iter().map(|x| (x.name.as_str(), &x.source)),
+            diagnostics,
+        );
+    }
+
+    if !config
+        .database
+        .iter()
+        .map(|x| x.name.as_str())
+        .check_all_unique_names()
+    {
+        report_duplicate_names(
+            config.database.iter().map(|x| (x.name.as_str(), &x.source)),
+            diagnostics,
+        );
+    }
+
+    for api in config.api.iter() {
+        validate_api(config, api, diagnostics);
+    }
+
+    validate_base_paths(config, diagnostics);
+    validate_domain_endpoint_types(config, diagnostics);
+}
+
+/// APIs can share a domain as long as they're served from different base paths (their `prefix`)
+fn validate_base_paths(config: &CloudConfig, diagnostics: &mut Diagnostics) {
+    let mut claimed = HashSet::new();
+
+    for api in config.api.iter() {
+        let Some(domain) = &api.domain else {
+            continue;
+        };
+
+        let url = format!("{domain}{}", api.prefix);
+        if !claimed.insert(url.clone()) {
+            diagnostics.push(
+                Diagnostic::at(&api.source, ApiDefinitionError::DuplicateBasePath(url))
+                    .with_key("prefix"),
+            );
+        }
+    }
+}
+
+/// A custom domain is either regional or edge-optimized, so every API on it has to be the same
+fn validate_domain_endpoint_types(config: &CloudConfig, diagnostics: &mut Diagnostics) {
+    let mut endpoint_types: HashMap<&str, EndpointType> = HashMap::new();
+
+    for api in config.api.iter() {
+        let Some(domain) = &api.domain else {
+            continue;
+        };
+
+        let existing = *endpoint_types.entry(domain).or_insert(api.endpoint_type);
+        if existing != api.endpoint_type {
+            diagnostics.push(
+                Diagnostic::at(
+                    &api.source,
+                    ApiDefinitionError::ConflictingEndpointTypes {
+                        domain: domain.clone(),
+                        existing,
+                        found: api.endpoint_type,
+                    },
+                )
+                .with_key("endpoint_type"),
+            );
+        }
+    }
+}
+
+/// Private APIs are only reachable through their VPC endpoints, so they need some + can't have a
+/// public domain
+fn validate_endpoint_type(api: &ApiConfig, diagnostics: &mut Diagnostics) {
+    let is_private = api.endpoint_type == EndpointType::Private;
+
+    if is_private && api.vpc_endpoint_ids.is_empty() {
+        diagnostics.push(
+            Diagnostic::at(&api.source, ApiDefinitionError::MissingVpcEndpoints)
+                .with_key("endpoint_type"),
+        );
+    }
+    if !is_private && !api.vpc_endpoint_ids.is_empty() {
+        diagnostics.push(
+            Diagnostic::at(
+                &api.source,
+                ApiDefinitionError::UnexpectedVpcEndpoints(api.endpoint_type),
+            )
+            .with_key("vpc_endpoint_ids"),
+        );
+    }
+    if is_private && api.domain.is_some() {
+        diagnostics.push(
+            Diagnostic::at(&api.source, ApiDefinitionError::PrivateApiWithDomain)
+                .with_key("domain"),
+        );
+    }
+}
+
+fn validate_api(config: &CloudConfig, api: &ApiConfig, diagnostics: &mut Diagnostics) {
+    validate_endpoint_type(api, diagnostics);
+
+    // The prefix is a base path mapping, which can't have parameters
+    let prefix_valid = api.prefix.is_valid() && api.prefix.parameter_names().next().is_none();
+    if !prefix_valid {
+        diagnostics.push(
+            Diagnostic::at(
+                &api.source,
+                ApiDefinitionError::InvalidPath(api.prefix.to_string()),
+            )
+            .with_key("prefix"),
+        );
+    }
+
+    // REST API base path mappings can't contain a `/`. Without a domain the prefix is part of each
+    // route instead, so it can be as long as needed.
+    if api.domain.is_some() && api.prefix.segments().len() > 1 {
+        diagnostics.push(
+            Diagnostic::at(
+                &api.source,
+                ApiDefinitionError::NestedBasePath(api.prefix.to_string()),
+            )
+            .with_key("prefix"),
+        );
+    }
+
+    if !api
+        .endpoints
+        .iter()
+        .map(|x| x.name.as_str())
+        .check_all_unique_names()
+    {
+        report_duplicate_names(
+            api.endpoints.iter().map(|x| (x.name.as_str(), &x.source)),
+            diagnostics,
+        );
+    }
+
+    let mut routes = HashSet::new();
+    for endpoint in api.endpoints.iter() {
+        let method: String = endpoint.method.into();
+        let route = format!("{method} {}", endpoint.route);
+        if !routes.insert(route.clone()) {
+            diagnostics.push(
+                Diagnostic::at(&endpoint.source, ApiDefinitionError::DuplicateRoute(route))
+                    .with_key("route"),
+            );
+        }
+
+        if !endpoint.route.is_valid() {
+            diagnostics.push(
+                Diagnostic::at(
+                    &endpoint.source,
+                    ApiDefinitionError::InvalidPath(endpoint.route.to_string()),
+                )
+                .with_key("route"),
+            );
+        }
+
+        if !endpoint.file.is_file() {
+            diagnostics.push(
+                Diagnostic::at(
+                    &endpoint.source,
+                    ApiDefinitionError::MissingHandler(endpoint.file.display().to_string()),
+                )
+                .with_key("file"),
+            );
+        }
+
+        validate_resource_refs(config, endpoint, diagnostics);
+    }
+
+    validate_path_parameters(api, diagnostics);
+}
+
+/// Makes sure every `read` + `write` entry (e.g. `"database.main"`) names a declared resource
+fn validate_resource_refs(
+    config: &CloudConfig,
+    endpoint: &ApiEndpoint,
+    diagnostics: &mut Diagnostics,
+) {
+    for (key, resources) in [("read", &endpoint.read), ("write", &endpoint.write)] {
+        for resource in resources {
+            let declared = match resource.split_once('.') {
+                Some(("database", name)) => config.database.iter().any(|x| x.name == name),
+                _ => false,
+            };
+
+            if !declared {
+                diagnostics.push(
+                    Diagnostic::at(
+                        &endpoint.source,
+                        ApiDefinitionError::UndeclaredResource(resource.clone()),
+                    )
+                    .with_key(key),
+                );
+            }
+        }
+    }
+}
+
+fn report_duplicate_names<'a>(
+    items: impl Iterator<Item = (&'a str, &'a SourceRef)>,
+    diagnostics: &mut Diagnostics,
+) {
+    let mut seen = HashSet::new();
+    for (name, source) in items {
+        if !seen.insert(name.to_lowercase()) {
+            diagnostics.push(
+                Diagnostic::at(
+                    source,
+                    ApiDefinitionError::DuplicateNameFound(name.to_owned()),
+                )
+                .with_key("name"),
+            );
+        }
+    }
+}
+
+/// API gateway only allows one parameter under each resource, so `posts/{id}` and
+/// `posts/{postId}/comments` can't both be routes. Parameter names also have to be unique within
+/// a route.
+fn validate_path_parameters(api: &ApiConfig, diagnostics: &mut Diagnostics) {
+    // The parameter used under each path, e.g. `posts` -> `{id}`
+    let mut parameters: HashMap<Route, PathSegment> = HashMap::new();
+
+    for endpoint in api.endpoints.iter() {
+        if !endpoint.route.is_valid() {
+            continue;
+        }
+
+        let segments = endpoint.route.segments();
+        let mut names = HashSet::new();
+        for (i, segment) in segments.iter().enumerate() {
+            let Some(name) = segment.parameter_name() else {
+                continue;
+            };
+
+            if !names.insert(name) {
+                diagnostics.push(
+                    Diagnostic::at(
+                        &endpoint.source,
+                        ApiDefinitionError::DuplicatePathParameter(
+                            name.to_owned(),
+                            endpoint.route.to_string(),
+                        ),
+                    )
+                    .with_key("route"),
+                );
+            }
+
+            let parent = Route(segments[..i].to_vec());
+            match parameters.get(&parent) {
+                Some(existing) if existing != segment => diagnostics.push(
+                    Diagnostic::at(
+                        &endpoint.source,
+                        ApiDefinitionError::ConflictingPathParameters {
+                            route: endpoint.route.to_string(),
+                            existing: existing.to_string(),
+                            found: segment.to_string(),
+                        },
+                    )
+                    .with_key("route"),
+                ),
+                Some(_) => {}
+                None => {
+                    parameters.insert(parent, segment.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::{cloud::api::HttpMethod, config::database::database_config::DatabaseConfig};
+
+    fn source(file: &str) -> SourceRef {
+        SourceRef {
+            file: PathBuf::from(file),
+            location: None,
+        }
+    }
+
+    fn config(api: Vec<ApiConfig>) -> CloudConfig {
+        CloudConfig {
+            project_name: "project".into(),
+            environment: None,
+            api,
+            database: vec![],
+            outputs: Default::default(),
+            terraform: Default::default(),
+        }
+    }
+
+    fn api(name: &str, endpoints: Vec<ApiEndpoint>) -> ApiConfig {
+        ApiConfig {
+            name: name.into(),
+            root: PathBuf::from("api"),
+            domain: None,
+            prefix: Route::default(),
+            endpoint_type: EndpointType::Regional,
+            vpc_endpoint_ids: vec![],
+            endpoints,
+            source: source("cloud.toml"),
+        }
+    }
+
+    /// An endpoint whose handler exists
+    fn endpoint(name: &str, method: HttpMethod, route: &str) -> ApiEndpoint {
+        ApiEndpoint {
+            name: name.into(),
+            method,
+            route: Route::parse(route),
+            file: Path::new(env!("CARGO_MANIFEST_DIR")).join("src/main.rs"),
+            read: vec![],
+            write: vec![],
+            source: source(&format!("api/{name}.toml")),
+        }
+    }
+
+    fn problems(config: &CloudConfig) -> Vec<String> {
+        let mut diagnostics = Diagnostics::new();
+        validate_config(config, &mut diagnostics);
+
+        diagnostics.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn accepts_valid_config() {
+        let config = config(vec![api(
+            "main",
+            vec![
+                endpoint("list", HttpMethod::Get, "posts"),
+                endpoint("create", HttpMethod::Post, "posts"),
+            ],
+        )]);
+
+        assert_eq!(problems(&config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_duplicate_names() {
+        let mut config = config(vec![
+            api(
+                "main",
+                vec![
+                    endpoint("list", HttpMethod::Get, "posts"),
+                    endpoint("List", HttpMethod::Get, "comments"),
+                ],
+            ),
+            api("Main", vec![]),
+        ]);
+        config.database = ["posts", "posts"]
+            .map(|name| DatabaseConfig {
+                name: name.into(),
+                engine: "dynamodb".into(),
+                source: source("cloud.toml"),
+            })
+            .to_vec();
+
+        assert_eq!(
+            problems(&config),
+            vec![
+                "cloud.toml: API definitions contain duplicate name: Main (at `name`)",
+                "cloud.toml: API definitions contain duplicate name: posts (at `name`)",
+                "api/List.toml: API definitions contain duplicate name: List (at `name`)",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_routes() {
+        let config = config(vec![api(
+            "main",
+            vec![
+                endpoint("list", HttpMethod::Get, "/posts/"),
+                endpoint("list_again", HttpMethod::Get, "posts"),
+                endpoint("any", HttpMethod::Any, "posts"),
+            ],
+        )]);
+
+        assert_eq!(
+            problems(&config),
+            vec!["api/list_again.toml: Another endpoint already handles GET /posts (at `route`)"]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_paths() {
+        let mut api = api(
+            "main",
+            vec![
+                endpoint("spaces", HttpMethod::Get, "my posts"),
+                endpoint("greedy", HttpMethod::Get, "{path+}/edit"),
+            ],
+        );
+        api.prefix = Route::parse("{version}");
+
+        assert_eq!(
+            problems(&config(vec![api])),
+            vec![
+                "cloud.toml: Not a valid API path: /{version} (at `prefix`)",
+                "api/spaces.toml: Not a valid API path: /my posts (at `route`)",
+                "api/greedy.toml: Not a valid API path: /{path+}/edit (at `route`)",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_handlers() {
+        let mut endpoint = endpoint("list", HttpMethod::Get, "posts");
+        endpoint.file = PathBuf::from("api/missing.js");
+
+        assert_eq!(
+            problems(&config(vec![api("main", vec![endpoint])])),
+            vec!["api/list.toml: Handler file api/missing.js does not exist (at `file`)"]
+        );
+    }
+
+    #[test]
+    fn reports_undeclared_resources() {
+        let mut endpoint = endpoint("list", HttpMethod::Get, "posts");
+        endpoint.read = vec!["database.posts".into(), "database.users".into()];
+        endpoint.write = vec!["posts".into()];
+
+        let mut config = config(vec![api("main", vec![endpoint])]);
+        config.database = vec![DatabaseConfig {
+            name: "posts".into(),
+            engine: "dynamodb".into(),
+            source: source("cloud.toml"),
+        }];
+
+        assert_eq!(
+            problems(&config),
+            vec![
+                "api/list.toml: database.users is not a declared resource (at `read`)",
+                "api/list.toml: posts is not a declared resource (at `write`)",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_conflicting_path_parameters() {
+        let config = config(vec![api(
+            "main",
+            vec![
+                endpoint("get", HttpMethod::Get, "posts/{id}"),
+                endpoint("comments", HttpMethod::Get, "posts/{postId}/comments"),
+                endpoint("delete", HttpMethod::Delete, "posts/{id}"),
+                endpoint("files", HttpMethod::Get, "posts/{path+}"),
+            ],
+        )]);
+
+        assert_eq!(
+            problems(&config),
+            vec![
+                "api/comments.toml: /posts/{postId}/comments uses the path parameter {postId} where other routes use {id} - every route has to use the same parameter name at the same position (at `route`)",
+                "api/files.toml: /posts/{path+} uses the path parameter {path+} where other routes use {id} - every route has to use the same parameter name at the same position (at `route`)",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_path_parameters() {
+        let config = config(vec![api(
+            "main",
+            vec![endpoint("get", HttpMethod::Get, "posts/{id}/comments/{id}")],
+        )]);
+
+        assert_eq!(
+            problems(&config),
+            vec!["api/get.toml: Path parameter {id} is used more than once in /posts/{id}/comments/{id} (at `route`)"]
+        );
+    }
+
+    fn on_domain(name: &str, domain: Option<&str>, prefix: &str) -> ApiConfig {
+        ApiConfig {
+            domain: domain.map(str::to_owned),
+            prefix: Route::parse(prefix),
+            ..api(name, vec![])
+        }
+    }
+
+    #[test]
+    fn reports_nested_base_paths() {
+        let config = config(vec![
+            on_domain("main", Some("example.com"), "api/v1"),
+            // Without a domain the prefix is part of each route instead
+            on_domain("internal", None, "api/v1"),
+        ]);
+
+        assert_eq!(
+            problems(&config),
+            vec!["cloud.toml: /api/v1 has more than one segment, which APIs on a custom domain can't be served from - use a single segment such as /v1 (at `prefix`)"]
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_base_paths() {
+        let config = config(vec![
+            on_domain("main", Some("example.com"), "api"),
+            on_domain("secondary", Some("example.com"), "v2"),
+            on_domain("copy", Some("example.com"), "/api/"),
+            on_domain("elsewhere", Some("example.org"), "api"),
+        ]);
+
+        assert_eq!(
+            problems(&config),
+            vec!["cloud.toml: Another API is already served from example.com/api (at `prefix`)"]
+        );
+    }
+
+    fn with_endpoint_type(
+        name: &str,
+        domain: Option<&str>,
+        endpoint_type: EndpointType,
+        vpc_endpoint_ids: &[&str],
+    ) -> ApiConfig {
+        ApiConfig {
+            endpoint_type,
+            vpc_endpoint_ids: vpc_endpoint_ids.iter().map(|x| x.to_string()).collect(),
+            ..on_domain(name, domain, "")
+        }
+    }
+
+    #[test]
+    fn accepts_private_apis_with_vpc_endpoints() {
+        let config = config(vec![with_endpoint_type(
+            "main",
+            None,
+            EndpointType::Private,
+            &["vpce-123"],
+        )]);
+
+        assert_eq!(problems(&config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_misconfigured_private_apis() {
+        let config = config(vec![
+            with_endpoint_type("main", Some("example.com"), EndpointType::Private, &[]),
+            with_endpoint_type("public", None, EndpointType::Edge, &["vpce-123"]),
+        ]);
+
+        assert_eq!(
+            problems(&config),
+            vec![
+                "cloud.toml: Private APIs need at least one VPC endpoint in vpc_endpoint_ids (at `endpoint_type`)",
+                "cloud.toml: Private APIs can't be served from a custom domain (at `domain`)",
+                "cloud.toml: Only private APIs are restricted to VPC endpoints, but this API is edge (at `vpc_endpoint_ids`)",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_conflicting_endpoint_types_on_a_domain() {
+        let config = config(vec![
+            with_endpoint_type("main", Some("example.com"), EndpointType::Regional, &[]),
+            ApiConfig {
+                prefix: Route::parse("v2"),
+                ..with_endpoint_type("secondary", Some("example.com"), EndpointType::Edge, &[])
+            },
+            with_endpoint_type("other", Some("example.org"), EndpointType::Edge, &[]),
+        ]);
+
+        assert_eq!(
+            problems(&config),
+            vec!["cloud.toml: APIs on example.com have to use the same endpoint type, but this one is edge and others are regional (at `endpoint_type`)"]
+        );
+    }
+}
@@ -0,0 +1,6 @@
+pub mod cli;
+pub mod cloud;
+pub mod config;
+pub mod database;
+pub mod stack;
+pub mod tf_generation;
@@ -1,12 +1,6 @@
-use std::collections::HashMap;
+use crate::stack::tf::{Terraform, TerraformEntity, TfBody, TfField, TfResource};
 
-use crate::stack::{
-    api::endpoint::ApiGatewayIntegration,
-    lambda::LambdaFunction,
-    tf::{Terraform, TerraformEntity, TfField, TfResource, TfVar},
-};
-
-use super::{endpoint::ApiEndpoint, gateway::ApiGateway};
+use super::gateway::ApiGateway;
 
 pub struct GatewayDeployment<'a> {
     pub gateway: &'a ApiGateway,
@@ -44,47 +38,57 @@ impl<'a> GatewayDeployment<'a> {
           }
                       }
                       */
+        let mut lifecycle = TfBody::new();
+        lifecycle.set_attribute("create_before_destroy", TfField::Bool(true));
+
         let mut resource = TfResource::new_resource(Self::tf_type(), self.tf_identifier());
         resource
             .add_field(
                 "rest_api_id",
                 self.gateway.var_gateway_rest_api("id").into(),
             )
-            .add_field(
-                "lifecycle",
-                TfField::map(vec![(
-                    "create_before_destroy".to_string(),
-                    TfField::Raw("true".into()),
-                )]),
-            );
+            .add_block("lifecycle", lifecycle);
 
-        for endpoint in self.gateway.endpoints.iter() {
-            resource.depends_on(endpoint);
+        if !self.gateway.endpoints.is_empty() {
+            let depends_on = self
+                .gateway
+                .endpoints
+                .iter()
+                .map(|x| TfField::Address(x.address()))
+                .collect();
+            resource.add_field("depends_on", TfField::List(depends_on));
         }
 
-        let redeployment_json = self
+        let mut redeployment = self
             .gateway
             .endpoints
             .iter()
-            .map(|x| {
-                format!(
-                    "{}.{},\n{}.{}",
-                    ApiEndpoint::tf_type(),
-                    x.tf_identifier(),
-                    ApiGatewayIntegration::tf_type(),
-                    x.api_integration().tf_identifier()
-                )
+            .flat_map(|x| {
+                [
+                    TfField::Address(x.address()),
+                    TfField::Address(x.api_integration().address()),
+                ]
             })
-            .collect::<Vec<String>>()
-            .join(",\n");
+            .collect::<Vec<TfField>>();
 
-        let mut t_map = HashMap::new();
-        t_map.insert(
-            "redeployment".to_string(),
-            TfField::Raw(format!("sha1(jsonencode([\n{redeployment_json}\n]))")),
-        );
+        // A new policy only takes effect once the gateway is redeployed
+        if let Some(policy) = self.gateway.policy() {
+            redeployment.push(TfField::Address(policy.address()));
+        }
 
-        resource.add_field("triggers", TfField::Object(t_map));
+        resource.add_field(
+            "triggers",
+            TfField::object(vec![(
+                "redeployment",
+                TfField::call(
+                    "sha1",
+                    vec![TfField::call(
+                        "jsonencode",
+                        vec![TfField::List(redeployment)],
+                    )],
+                ),
+            )]),
+        );
 
         resource.create_terraform()
     }
@@ -1,26 +1,107 @@
-use std::hash::{DefaultHasher, Hash, Hasher};
-
 use crate::stack::{
-    tf::{Terraform, TfField, TfResource, TfVar},
+    registry::{Stack, StackEntity},
+    tf::{
+        identifier::Identifiers, SensitiveOutputs, Terraform, TfBody, TfField, TfOutput,
+        TfResource, TfVar,
+    },
     Shared,
 };
 
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub enum EndpointConfiguration {
+    /// Served from the region the API is deployed to
+    #[default]
+    Regional,
+    /// Served through CloudFront
+    Edge,
+    /// Only reachable through these VPC endpoints, so never publicly routable. Private APIs can't
+    /// have a custom domain.
+    Private { vpc_endpoint_ids: Vec<String> },
+}
+
+impl EndpointConfiguration {
+    pub fn to_tf_string(&self) -> &'static str {
+        match self {
+            Self::Regional => "REGIONAL",
+            Self::Edge => "EDGE",
+            Self::Private { .. } => "PRIVATE",
+        }
+    }
+
+    /// `endpoint_configuration { ... }` for a gateway or domain
+    pub fn to_body(&self) -> TfBody {
+        let mut body = TfBody::new();
+        body.set_attribute(
+            "types",
+            TfField::List(vec![TfField::String(self.to_tf_string().into())]),
+        );
+
+        if let Self::Private { vpc_endpoint_ids } = self {
+            body.set_attribute(
+                "vpc_endpoint_ids",
+                TfField::List(
+                    vpc_endpoint_ids
+                        .iter()
+                        .map(|x| TfField::String(x.clone()))
+                        .collect(),
+                ),
+            );
+        }
+
+        body
+    }
+
+    /// How a custom domain for an API with this configuration is served, or `None` for private
+    /// APIs, which can't have one
+    pub fn domain_endpoint(&self) -> Option<DomainEndpoint> {
+        match self {
+            Self::Regional => Some(DomainEndpoint::Regional),
+            Self::Edge => Some(DomainEndpoint::Edge),
+            Self::Private { .. } => None,
+        }
+    }
+}
+
+/// How a custom domain is served - the [`EndpointConfiguration`]s a domain can have
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DomainEndpoint {
     #[default]
     Regional,
+    /// CloudFront only uses certificates from us-east-1. That's the only [`Region`] a stack can be
+    /// deployed to, so the certificate is read with the default provider.
+    ///
+    /// [`Region`]: crate::stack::region::Region
+    Edge,
+}
+
+impl From<DomainEndpoint> for EndpointConfiguration {
+    fn from(value: DomainEndpoint) -> Self {
+        match value {
+            DomainEndpoint::Regional => Self::Regional,
+            DomainEndpoint::Edge => Self::Edge,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Certificate {
     pub domain: String,
+    pub identifiers: Identifiers,
 }
 
 impl Certificate {
+    pub fn new(domain: impl Into<String>, identifiers: &Identifiers) -> Self {
+        let domain = domain.into();
+        identifiers.register("certificate", &[&domain]);
+
+        Self {
+            domain,
+            identifiers: identifiers.clone(),
+        }
+    }
+
     fn unique_key(&self) -> String {
-        let mut hasher = DefaultHasher::default();
-        self.domain.hash(&mut hasher);
-        hasher.finish().to_string()
+        self.identifiers.key("certificate", &[&self.domain])
     }
 
     pub fn tf_identifier(&self) -> String {
@@ -50,8 +131,9 @@ impl Certificate {
 
 #[derive(Debug, Clone)]
 pub struct Domain {
-    pub endpoint_configuration: EndpointConfiguration,
+    pub endpoint: DomainEndpoint,
     pub certificate: Shared<Certificate>,
+    pub sensitive_outputs: SensitiveOutputs,
 }
 
 impl Domain {
@@ -72,29 +154,52 @@ impl Domain {
     }
 
     pub fn create_terraform(&self) -> Terraform {
-        let endpoint_cfg_type = match self.endpoint_configuration {
-            EndpointConfiguration::Regional => "REGIONAL",
-        };
-
         let cert = self.certificate.borrow();
 
-        TfResource::new_resource("aws_api_gateway_domain_name", &self.tf_identifier())
+        // Edge domains are served through CloudFront, so their DNS records point there instead
+        let (certificate_field, target) = match self.endpoint {
+            DomainEndpoint::Regional => ("regional_certificate_arn", "regional"),
+            DomainEndpoint::Edge => ("certificate_arn", "cloudfront"),
+        };
+
+        TfResource::new_resource("aws_api_gateway_domain_name", self.tf_identifier())
             .add_field("domain_name", TfField::String(cert.domain.clone()))
-            .add_field(
-                "regional_certificate_arn",
-                TfField::Variable(cert.var("arn")),
-            )
-            .add_field(
+            .add_field(certificate_field, TfField::Variable(cert.var("arn")))
+            .add_block(
                 "endpoint_configuration",
-                TfField::Map(
-                    vec![(
-                        "types".into(),
-                        TfField::List(vec![TfField::String(endpoint_cfg_type.to_string())]),
-                    )]
-                    .into_iter()
-                    .collect(),
-                ),
+                EndpointConfiguration::from(self.endpoint).to_body(),
             )
             .create_terraform()
+            .combine(
+                &TfOutput::of(self.var(format!("{target}_domain_name")))
+                    .description(format!("Target for {}'s DNS record", cert.domain))
+                    .sensitive_if_in(&self.sensitive_outputs)
+                    .create_terraform(),
+            )
+            .combine(
+                &TfOutput::of(self.var(format!("{target}_zone_id")))
+                    .description(format!(
+                        "Hosted zone id for {}'s DNS alias record",
+                        cert.domain
+                    ))
+                    .sensitive_if_in(&self.sensitive_outputs)
+                    .create_terraform(),
+            )
+    }
+}
+
+impl StackEntity for Certificate {
+    fn create_terraform(&self) -> Terraform {
+        Certificate::create_terraform(self)
+    }
+}
+
+impl StackEntity for Domain {
+    fn register_dependencies(&self, stack: &mut Stack) {
+        stack.add_shared(&self.certificate);
+    }
+
+    fn create_terraform(&self) -> Terraform {
+        Domain::create_terraform(self)
     }
 }
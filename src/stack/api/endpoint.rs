@@ -1,18 +1,19 @@
 use crate::{
-    cloud::api::HttpMethod,
+    cloud::api::{HttpMethod, Route},
     stack::{
         lambda::LambdaFunction,
-        tf::{Terraform, TerraformEntity, TfField, TfResource},
+        tf::{Terraform, TerraformEntity, TfField, TfResource, TfVar},
     },
 };
 
-use super::gateway::{ApiGateway, ResourcePath};
+use super::gateway::ApiGateway;
 
 #[derive(Clone, Debug)]
 pub struct ApiEndpoint {
     pub lambda: LambdaFunction,
     pub http_method: HttpMethod,
-    pub route: String,
+    /// Relative to the gateway's base path
+    pub route: Route,
 }
 
 impl TerraformEntity for ApiEndpoint {
@@ -23,7 +24,7 @@ impl TerraformEntity for ApiEndpoint {
         "aws_api_gateway_method"
     }
     fn tf_identifier(&self) -> String {
-        format!("endpoint_{}", self.lambda.tf_identifier())
+        format!("endpoint_{}", self.unique_key())
     }
 }
 
@@ -40,16 +41,12 @@ impl<'a> TerraformEntity for ApiGatewayIntegration<'a> {
         "aws_api_gateway_integration"
     }
     fn tf_identifier(&self) -> String {
-        format!("integration_{}", self.api_endpoint.tf_identifier())
+        format!("integration_{}", self.api_endpoint.unique_key())
     }
 }
 
 impl<'a> ApiGatewayIntegration<'a> {
-    pub fn create_terraform(
-        &self,
-        gateway: &ApiGateway,
-        resource_path: &ResourcePath,
-    ) -> Terraform {
+    pub fn create_terraform(&self, gateway: &ApiGateway, resource_id: &TfVar) -> Terraform {
         let http_method = self.http_method.into();
 
         let mut tf_gateway_integration =
@@ -60,10 +57,13 @@ impl<'a> ApiGatewayIntegration<'a> {
                 "rest_api_id",
                 TfField::Variable(gateway.var_gateway_rest_api("id")),
             )
-            .add_field("resource_id", TfField::Variable(resource_path.var("id")))
+            .add_field("resource_id", TfField::Variable(resource_id.clone()))
             .add_field("http_method", TfField::String(http_method))
             // May not need this depends_on?
-            .depends_on(&self.api_endpoint.lambda)
+            .add_field(
+                "depends_on",
+                TfField::List(vec![TfField::Address(self.api_endpoint.lambda.address())]),
+            )
             // lambda can only be invoked w/ POST requests, so this turns the "GET" into a "POST" the lambda can handle
             .add_field("integration_http_method", TfField::String("POST".into()))
             .add_field("type", TfField::String("AWS_PROXY".into()))
@@ -80,22 +80,33 @@ impl ApiEndpoint {
         self.lambda.unique_key()
     }
 
-    pub fn zip_file(&self, path: &str) -> std::io::Result<()> {
-        self.lambda.zip_file(path)
+    pub fn zip(&self) -> anyhow::Result<Vec<u8>> {
+        self.lambda.zip()
+    }
+
+    /// An endpoint is recognised by either the URL it's served from or its handler, so changing
+    /// one of those (or renaming the API) doesn't replace its lambda
+    pub fn logical_keys(&self, gateway: &ApiGateway) -> Vec<String> {
+        let method: String = self.http_method.into();
+
+        vec![
+            format!(
+                "route:{}:{method} {}",
+                gateway.domain_name().unwrap_or_default(),
+                gateway.public_route(&self.route)
+            ),
+            format!("file:{}", self.lambda.file_path),
+        ]
     }
 
     pub fn api_integration(&self) -> ApiGatewayIntegration<'_> {
         ApiGatewayIntegration {
-            http_method: self.http_method.clone(),
+            http_method: self.http_method,
             api_endpoint: self,
         }
     }
 
-    pub fn create_terraform(
-        &self,
-        gateway: &ApiGateway,
-        resource_path: &ResourcePath,
-    ) -> Terraform {
+    pub fn create_terraform(&self, gateway: &ApiGateway, resource_id: TfVar) -> Terraform {
         let lambda_tf = self.lambda.create_terraform();
 
         let http_method: String = self.http_method.into();
@@ -106,10 +117,23 @@ impl ApiEndpoint {
                 "rest_api_id",
                 TfField::Variable(gateway.var_gateway_rest_api("id")),
             )
-            .add_field("resource_id", TfField::Variable(resource_path.var("id")))
+            .add_field("resource_id", TfField::Variable(resource_id.clone()))
             .add_field("http_method", TfField::String(http_method.clone()))
             .add_field("authorization", TfField::String("NONE".into()));
 
+        let path_parameters = self.route.parameter_names().collect::<Vec<_>>();
+        if !path_parameters.is_empty() {
+            tf_gateway_method.add_field(
+                "request_parameters",
+                TfField::object(
+                    path_parameters
+                        .iter()
+                        .map(|x| (format!("method.request.path.{x}"), TfField::Bool(true)))
+                        .collect(),
+                ),
+            );
+        }
+
         let gw_integration = self.api_integration();
 
         let permission_tf = TfResource::new_resource("aws_lambda_permission", self.tf_identifier())
@@ -128,40 +152,16 @@ impl ApiEndpoint {
             )
             .add_field(
                 "source_arn",
-                TfField::String(format!(
-                    "${{{}}}/*/*/*",
-                    gateway.var_gateway_rest_api("execution_arn").to_tf_string()
-                )),
+                TfField::template(vec![
+                    gateway.var_gateway_rest_api("execution_arn").into(),
+                    "/*/*/*".into(),
+                ]),
             )
             .create_terraform();
 
-        //         resource "aws_api_gateway_deployment" "api_deployment_{api_identifier}_{function_name}" {
-        //   depends_on = [
-        //     {depends_on}
-        //   ] # [aws_api_gateway_integration.lambda_integration_{api_identifier}_{function_name}]
-        //
-        //   rest_api_id = aws_api_gateway_rest_api.api_gateway.id
-        //   # https://stackoverflow.com/questions/48955987/missing-authentication-token-on-unauthenticated-method
-        //   # This being a fixed thing is preventing re-applies from working properly
-        //   stage_name  = var.api_gateway_stage_name # should use aws_api_gateway_stage resource instead.
-        // }
-        // let deployment =
-        //     TfResource::new_resource("aws_api_gateway_deployment", self.tf_identifier())
-        //         .depends_on(&gw_integration)
-        //         .add_field(
-        //             "rest_api_id",
-        //             TfField::Variable(gateway.var_gateway_rest_api("id")),
-        //         )
-        //         .add_field(
-        //             "stage_name",
-        //             TfField::Variable(gateway.var_path_mapping_resource("stage_name")),
-        //         )
-        //         .create_terraform();
-
         lambda_tf
             .combine(&tf_gateway_method.create_terraform())
-            .combine(&gw_integration.create_terraform(gateway, resource_path))
+            .combine(&gw_integration.create_terraform(gateway, &resource_id))
             .combine(&permission_tf)
-        // .combine(&deployment)
     }
 }
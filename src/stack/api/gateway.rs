@@ -1,15 +1,23 @@
-use std::{
-    collections::{HashMap, HashSet},
-    hash::{DefaultHasher, Hash, Hasher},
-};
-
-use crate::stack::{
-    tf::{Terraform, TerraformEntity, TfField, TfResource, TfVar},
-    Shared,
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    cloud::api::{PathSegment, Route},
+    stack::{
+        registry::{Stack, StackEntity},
+        tf::{
+            identifier::Identifiers, SensitiveOutputs, Terraform, TerraformEntity, TfField,
+            TfOutput, TfResource, TfVar,
+        },
+        Shared,
+    },
 };
 
 use super::{
-    deployment::GatewayDeployment, domain_name::Domain, endpoint::ApiEndpoint, stage::Stage,
+    deployment::GatewayDeployment,
+    domain_name::{Domain, EndpointConfiguration},
+    endpoint::ApiEndpoint,
+    policy::GatewayPolicy,
+    stage::Stage,
 };
 
 #[derive(Default, Debug, Clone)]
@@ -17,26 +25,31 @@ pub struct ApiGateway {
     pub name: String,
     pub domain: Option<Shared<Domain>>,
     pub stage_name: String,
+    /// Where the gateway is served from on its domain, e.g. `/api`. Base path mappings can only
+    /// have one segment.
+    pub base_path: Route,
+    /// What every endpoint's route starts with, e.g. `/api`, when the gateway has no domain to
+    /// serve it from as a base path
+    pub route_prefix: Route,
+    pub endpoint_configuration: EndpointConfiguration,
 
     pub endpoints: Vec<ApiEndpoint>,
+    pub identifiers: Identifiers,
+    pub sensitive_outputs: SensitiveOutputs,
 }
 
 #[derive(Debug, Clone)]
 pub struct ResourcePath {
+    /// See [`ApiGateway::resource_path_key`]
+    key: String,
     parent_id: TfVar,
-    resource_path: String,
+    /// The full route, e.g. `/posts/{id}`
+    resource_path: Route,
 }
 
 impl ResourcePath {
-    pub fn unique_id(&self) -> String {
-        let mut hasher = DefaultHasher::default();
-        self.resource_path.hash(&mut hasher);
-        let hash = hasher.finish();
-        hash.to_string()
-    }
-
     pub fn tf_identifier(&self) -> String {
-        format!("resource_path_{}", self.unique_id())
+        format!("resource_path_{}", self.key)
     }
 
     pub fn var(&self, field: impl Into<String>) -> TfVar {
@@ -47,6 +60,14 @@ impl ResourcePath {
         }
     }
 
+    /// The last segment of the route, e.g. `{id}`
+    fn path_part(&self) -> String {
+        self.resource_path
+            .last()
+            .map(|x| x.to_string())
+            .expect("The root of a gateway isn't a resource path")
+    }
+
     fn create_terraform(&self, gateway: &ApiGateway) -> Terraform {
         /*
                 resource "aws_api_gateway_resource" "api_resource_{resource_path_hash}" {
@@ -61,14 +82,24 @@ impl ResourcePath {
                 TfField::Variable(gateway.var_gateway_rest_api("id")),
             )
             .add_field("parent_id", TfField::Variable(self.parent_id.clone()))
-            .add_field("path_part", TfField::String(self.resource_path.clone()))
+            .add_field("path_part", TfField::String(self.path_part()))
             .create_terraform()
     }
 }
 
+/// The route as it's named in the resource path's identifier, e.g. `posts/param_id`
+fn resource_path_name(route: &Route) -> String {
+    route
+        .segments()
+        .iter()
+        .map(PathSegment::identifier_part)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 #[derive(Debug, Default)]
 struct PathNode {
-    children: HashMap<String, PathNode>,
+    children: BTreeMap<PathSegment, PathNode>,
 }
 
 impl PathNode {
@@ -76,17 +107,15 @@ impl PathNode {
         Self::default()
     }
 
-    // Add a path to the tree
-    fn add_path(&mut self, path: &str) {
+    // Add a route to the tree
+    fn add_path(&mut self, route: &Route) {
         let mut current_node = self;
 
-        // Split the path into segments and iterate over them
-        for segment in path.split('/') {
-            // Insert the segment into the current node's children if it doesn't exist
-            current_node = current_node
-                .children
-                .entry(segment.to_string())
-                .or_insert_with(|| Default::default())
+        for segment in route.segments() {
+            // Insert the segment into the current node's children if it doesn't exist. Parameters
+            // that would clash with each other (`{id}` next to `{postId}`) are caught when the
+            // config is validated.
+            current_node = current_node.children.entry(segment.clone()).or_default()
         }
     }
 }
@@ -95,47 +124,24 @@ fn recurse(
     gateway: &ApiGateway,
     route_tree: &PathNode,
     path_so_far: Option<&ResourcePath>,
-    route_so_far: &str,
+    route_so_far: &Route,
 ) -> Vec<ResourcePath> {
     let mut paths = vec![];
 
     for (subroute, children) in &route_tree.children {
-        let route_here = if route_so_far.is_empty() {
-            subroute.to_owned()
-        } else {
-            format!("{route_so_far}/{subroute}")
-        };
-
-        // let mut resource_path = fs::read_to_string("terraform/lambda_test/resource_path.tf")
-        //     .expect("Cannot load resource_path.tf");
+        let route_here = route_so_far.child(subroute.clone());
 
         let parent_id = if let Some(path_so_far) = path_so_far {
             path_so_far.var("id")
         } else {
             gateway.var_gateway_rest_api("root_resource_id")
         };
-        // else {
-        //     let mut hasher = DefaultHasher::default();
-        //     route_so_far.hash(&mut hasher);
-        //     let hash_so_far = hasher.finish();
-        //     format!("aws_api_gateway_resource.api_resource_{hash_so_far}.id")
-        // };
         let rp = ResourcePath {
+            key: gateway.resource_path_key(&route_here),
             parent_id,
             resource_path: route_here.clone(),
         };
 
-        // resource_path = resource_path
-        //     .replace("{resource_path_hash}", &format!("{hash_here}"))
-        //     .replace("{resource_path}", &subroute)
-        //     .replace("{parent_id}", &parent_id);
-        //
-        // fs::write(
-        //     &format!("{root_dir}/resource_path_{hash_here}.tf"),
-        //     resource_path,
-        // )
-        // .expect("Failed to write resource_path.tf");
-
         paths.append(&mut recurse(gateway, children, Some(&rp), &route_here));
 
         paths.push(rp);
@@ -146,16 +152,80 @@ fn recurse(
 
 impl ApiGateway {
     fn unique_key(&self) -> String {
-        let mut hasher = DefaultHasher::default();
-        self.name.hash(&mut hasher);
-        self.stage_name.hash(&mut hasher);
-        hasher.finish().to_string()
+        self.identifiers.key("gateway", &[&self.name])
+    }
+
+    /// Records the names of this gateway, its stage, resource paths and lambdas, so any that
+    /// collide with other resources' names can be told apart
+    pub fn register_identifiers(&self) {
+        self.identifiers.register("gateway", &[&self.name]);
+        self.identifiers
+            .register("stage", &[&self.name, &self.stage_name]);
+
+        for endpoint in self.endpoints.iter() {
+            endpoint.lambda.register_identifier();
+
+            for len in 1..=endpoint.route.segments().len() {
+                let route = Route(endpoint.route.segments()[..len].to_vec());
+                self.identifiers
+                    .register("resource_path", &[&self.name, &resource_path_name(&route)]);
+            }
+        }
+    }
+
+    /// Tells the `aws_api_gateway_resource` for this route apart from the gateway's others
+    fn resource_path_key(&self, route: &Route) -> String {
+        self.identifiers
+            .key("resource_path", &[&self.name, &resource_path_name(route)])
     }
 
     pub fn tf_identifier(&self) -> String {
         format!("gateway_{}", self.unique_key())
     }
 
+    /// The custom domain this gateway is served from, if any
+    pub fn domain_name(&self) -> Option<String> {
+        self.domain
+            .as_ref()
+            .map(|x| x.borrow().certificate.borrow().domain.clone())
+    }
+
+    /// A gateway is recognised by its domain + the endpoints it serves, so renaming the API
+    /// doesn't replace it
+    fn logical_keys(&self) -> Vec<String> {
+        let mut routes = self
+            .endpoints
+            .iter()
+            .map(|x| {
+                let method: String = x.http_method.into();
+                format!("{method} {}", self.public_route(&x.route))
+            })
+            .collect::<Vec<_>>();
+        routes.sort();
+
+        vec![format!(
+            "gateway:{}:{}",
+            self.domain_name().unwrap_or_default(),
+            routes.join(",")
+        )]
+    }
+
+    /// Where `route` is served from on the gateway's domain, i.e. under its base path
+    pub fn public_route(&self, route: &Route) -> Route {
+        self.base_path.join(route)
+    }
+
+    /// The resource policy restricting who can invoke this gateway, if it needs one
+    pub fn policy(&self) -> Option<GatewayPolicy<'_>> {
+        match &self.endpoint_configuration {
+            EndpointConfiguration::Private { vpc_endpoint_ids } => Some(GatewayPolicy {
+                gateway: self,
+                vpc_endpoint_ids,
+            }),
+            EndpointConfiguration::Regional | EndpointConfiguration::Edge => None,
+        }
+    }
+
     pub fn var_gateway_rest_api(&self, field: impl Into<String>) -> TfVar {
         TfVar::Resource {
             resource_name: "aws_api_gateway_rest_api".into(),
@@ -182,52 +252,88 @@ impl ApiGateway {
 
     pub fn create_terraform(&self) -> Terraform {
         // resource "aws_api_gateway_rest_api" "api_gateway"
-        let mut gateway_resource = TfResource::new_resource(
-            "aws_api_gateway_rest_api",
-            format!("{}", self.tf_identifier()),
-        );
-        gateway_resource.add_field("name", TfField::String(self.name.clone()));
+        let mut gateway_resource =
+            TfResource::new_resource("aws_api_gateway_rest_api", self.tf_identifier());
+        gateway_resource
+            .add_field("name", TfField::String(self.name.clone()))
+            .add_block(
+                "endpoint_configuration",
+                self.endpoint_configuration.to_body(),
+            );
 
         let stage = Stage {
             stage_name: self.stage_name.clone(),
+            gateway_name: self.name.clone(),
+            identifiers: self.identifiers.clone(),
         };
 
-        let Some(domain) = self.domain.as_ref() else {
-            todo!();
-        };
-        let mut path_mapping_resource = TfResource::new_resource(
-            "aws_api_gateway_base_path_mapping",
-            format!("{}", self.tf_identifier()),
-        );
-        path_mapping_resource
-            .add_field(
-                "domain_name",
-                TfField::Variable(domain.borrow().var("domain_name")),
-            )
-            .add_field("api_id", TfField::Variable(self.var_gateway_rest_api("id")))
-            .add_field("stage_name", stage.var("stage_name").into());
+        let mut gateway_tf = gateway_resource.create_terraform();
 
-        let gateway_tf = gateway_resource
-            .create_terraform()
-            .combine(&path_mapping_resource.create_terraform());
+        if let Some(policy) = self.policy() {
+            gateway_tf = gateway_tf.combine(&policy.create_terraform());
+        }
+
+        let url = match &self.domain {
+            Some(domain) => {
+                let domain = domain.borrow();
+                let mut path_mapping_resource = TfResource::new_resource(
+                    "aws_api_gateway_base_path_mapping",
+                    self.tf_identifier(),
+                );
+                path_mapping_resource
+                    .add_field("domain_name", TfField::Variable(domain.var("domain_name")))
+                    .add_field("api_id", TfField::Variable(self.var_gateway_rest_api("id")))
+                    .add_field("stage_name", stage.var("stage_name").into());
+                if !self.base_path.is_root() {
+                    path_mapping_resource
+                        .add_field("base_path", TfField::String(self.base_path.path()));
+                }
+                gateway_tf = gateway_tf.combine(&path_mapping_resource.create_terraform());
+
+                TfField::template(vec![
+                    "https://".into(),
+                    domain.var("domain_name").into(),
+                    self.base_path.to_string().into(),
+                ])
+            }
+            // Served from the stage's own `execute-api` URL. The URL points at the root of the API
+            // either way, so it includes the prefix.
+            None if self.route_prefix.is_root() => TfField::Variable(stage.var("invoke_url")),
+            None => TfField::template(vec![
+                stage.var("invoke_url").into(),
+                self.route_prefix.to_string().into(),
+            ]),
+        };
 
+        let gateway_tf = gateway_tf.combine(
+            &TfOutput::new(format!("{}_url", self.tf_identifier()), url)
+                .description(format!("URL the {} API is served from", self.name))
+                .sensitive_if_in(&self.sensitive_outputs)
+                .create_terraform(),
+        );
         let routes = self
             .endpoints
             .iter()
-            .map(|x| x.route.as_str())
-            .collect::<HashSet<&str>>();
+            .map(|x| &x.route)
+            .collect::<BTreeSet<&Route>>();
 
         let mut route_tree = PathNode::new();
 
         for route in routes.iter() {
-            route_tree.add_path(&route);
+            route_tree.add_path(route);
         }
 
-        let resource_paths = recurse(self, &route_tree, None, "");
+        let resource_paths = recurse(self, &route_tree, None, &Route::default());
 
         let resource_tf = resource_paths
             .iter()
-            .map(|x| x.create_terraform(self))
+            .map(|x| {
+                x.create_terraform(self).identified_by(&[format!(
+                    "path:{}:{}",
+                    self.domain_name().unwrap_or_default(),
+                    self.public_route(&x.resource_path)
+                )])
+            })
             .reduce(|a, b| a.combine(&b))
             .unwrap_or(Terraform::default());
 
@@ -238,11 +344,19 @@ impl ApiGateway {
             .endpoints
             .iter()
             .map(|endpoint| {
-                let path = resource_paths
-                    .iter()
-                    .find(|x| x.resource_path == endpoint.route)
-                    .unwrap_or_else(|| panic!("Failed to find resource path for endpoint! {resource_paths:?} - {endpoint:?}"));
-                endpoint.create_terraform(self, path)
+                // Endpoints at the root of the API are methods on the gateway's root resource
+                let resource_id = if endpoint.route.is_root() {
+                    self.var_gateway_rest_api("root_resource_id")
+                } else {
+                    resource_paths
+                        .iter()
+                        .find(|x| x.resource_path == endpoint.route)
+                        .unwrap_or_else(|| panic!("Failed to find resource path for endpoint! {resource_paths:?} - {endpoint:?}"))
+                        .var("id")
+                };
+                endpoint
+                    .create_terraform(self, resource_id)
+                    .identified_by(&endpoint.logical_keys(self))
             })
             .reduce(|a, b| a.combine(&b))
             .unwrap_or(Terraform::default());
@@ -251,8 +365,26 @@ impl ApiGateway {
 
         gateway_tf
             .combine(&stage_tf)
+            .combine(&deployment_tf)
+            .identified_by(&self.logical_keys())
             .combine(&resource_tf)
             .combine(&endpoints_tf)
-            .combine(&deployment_tf)
+            .in_file(&format!("api_{}", self.unique_key()))
+    }
+}
+
+impl StackEntity for ApiGateway {
+    fn register_dependencies(&self, stack: &mut Stack) {
+        if let Some(domain) = &self.domain {
+            stack.add_shared(domain);
+        }
+
+        for endpoint in self.endpoints.iter() {
+            stack.add_shared(&endpoint.lambda.role);
+        }
+    }
+
+    fn create_terraform(&self) -> Terraform {
+        ApiGateway::create_terraform(self)
     }
 }
@@ -3,4 +3,5 @@ pub mod deployment;
 pub mod domain_name;
 pub mod endpoint;
 pub mod gateway;
+pub mod policy;
 pub mod stage;
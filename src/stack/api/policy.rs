@@ -0,0 +1,102 @@
+use crate::stack::tf::{Terraform, TerraformEntity, TfField, TfResource};
+
+use super::gateway::ApiGateway;
+
+/// The resource policy of a private gateway, which only lets its VPC endpoints invoke it
+pub struct GatewayPolicy<'a> {
+    pub gateway: &'a ApiGateway,
+    pub vpc_endpoint_ids: &'a [String],
+}
+
+impl<'a> TerraformEntity for GatewayPolicy<'a> {
+    fn tf_type() -> &'static str {
+        "aws_api_gateway_rest_api_policy"
+    }
+    fn tf_identifier(&self) -> String {
+        self.gateway.tf_identifier()
+    }
+    fn data_type() -> crate::stack::tf::TfDataType {
+        crate::stack::tf::TfDataType::Resource
+    }
+}
+
+impl<'a> GatewayPolicy<'a> {
+    pub fn create_terraform(&self) -> Terraform {
+        // resource "aws_api_gateway_rest_api_policy" "gateway_123" {
+        //   rest_api_id = aws_api_gateway_rest_api.gateway_123.id
+        //
+        //   policy = jsonencode({
+        //     Version = "2012-10-17"
+        //     Statement = [
+        //       {
+        //         Effect    = "Deny"
+        //         Principal = "*"
+        //         Action    = "execute-api:Invoke"
+        //         Resource  = "${aws_api_gateway_rest_api.gateway_123.execution_arn}/*"
+        //         Condition = {
+        //           StringNotEquals = {
+        //             "aws:SourceVpce" = ["vpce-123"]
+        //           }
+        //         }
+        //       },
+        //       {
+        //         Effect    = "Allow"
+        //         ...
+        //       }
+        //     ]
+        //   })
+        // }
+        let resource = TfField::template(vec![
+            self.gateway.var_gateway_rest_api("execution_arn").into(),
+            "/*".into(),
+        ]);
+
+        let statement = |effect: &str| {
+            vec![
+                ("Effect", TfField::String(effect.into())),
+                ("Principal", TfField::String("*".into())),
+                ("Action", TfField::String("execute-api:Invoke".into())),
+                ("Resource", resource.clone()),
+            ]
+        };
+
+        let vpc_endpoint_ids = self
+            .vpc_endpoint_ids
+            .iter()
+            .map(|x| TfField::String(x.clone()))
+            .collect();
+
+        // Anything not coming through one of the endpoints is denied, even if another policy
+        // would allow it
+        let mut deny = statement("Deny");
+        deny.push((
+            "Condition",
+            TfField::object(vec![(
+                "StringNotEquals",
+                TfField::object(vec![("aws:SourceVpce", TfField::List(vpc_endpoint_ids))]),
+            )]),
+        ));
+
+        let policy = TfField::call(
+            "jsonencode",
+            vec![TfField::object(vec![
+                ("Version", TfField::String("2012-10-17".into())),
+                (
+                    "Statement",
+                    TfField::List(vec![
+                        TfField::object(deny),
+                        TfField::object(statement("Allow")),
+                    ]),
+                ),
+            ])],
+        );
+
+        TfResource::new_resource(Self::tf_type(), self.tf_identifier())
+            .add_field(
+                "rest_api_id",
+                self.gateway.var_gateway_rest_api("id").into(),
+            )
+            .add_field("policy", policy)
+            .create_terraform()
+    }
+}
@@ -1,11 +1,13 @@
-use std::hash::{DefaultHasher, Hash, Hasher};
-
-use crate::stack::tf::{Terraform, TerraformEntity, TfField, TfResource};
+use crate::stack::tf::{
+    identifier::Identifiers, Terraform, TerraformEntity, TfField, TfOutput, TfResource,
+};
 
 use super::{deployment::GatewayDeployment, gateway::ApiGateway};
 
 pub struct Stage {
     pub stage_name: String,
+    pub gateway_name: String,
+    pub identifiers: Identifiers,
 }
 
 impl TerraformEntity for Stage {
@@ -16,9 +18,11 @@ impl TerraformEntity for Stage {
         crate::stack::tf::TfDataType::Resource
     }
     fn tf_identifier(&self) -> String {
-        let mut hasher = DefaultHasher::default();
-        self.stage_name.hash(&mut hasher);
-        format!("stage_{}", hasher.finish())
+        format!(
+            "stage_{}",
+            self.identifiers
+                .key("stage", &[&self.gateway_name, &self.stage_name])
+        )
     }
 }
 
@@ -43,5 +47,14 @@ impl Stage {
                 TfField::Variable(gateway.var_gateway_rest_api("id")),
             )
             .create_terraform()
+            .combine(
+                &TfOutput::of(self.var("invoke_url"))
+                    .description(format!(
+                        "URL the {} API's {} stage is served from",
+                        gateway.name, self.stage_name
+                    ))
+                    .sensitive_if_in(&gateway.sensitive_outputs)
+                    .create_terraform(),
+            )
     }
 }
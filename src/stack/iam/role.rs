@@ -1,6 +1,9 @@
-use std::hash::{DefaultHasher, Hash, Hasher};
-
-use crate::stack::tf::{Terraform, TfField, TfResource, TfVar};
+use crate::stack::{
+    registry::StackEntity,
+    tf::{
+        identifier::Identifiers, SensitiveOutputs, Terraform, TfField, TfOutput, TfResource, TfVar,
+    },
+};
 
 #[derive(Default, Debug, Clone)]
 pub enum RoleEffect {
@@ -41,20 +44,30 @@ impl RolePolicy {
 pub struct Role {
     pub name: String,
     pub policies: Vec<RolePolicy>,
+    pub identifiers: Identifiers,
+    pub sensitive_outputs: SensitiveOutputs,
 }
 
 impl Role {
-    pub fn new(name: impl Into<String>, policies: Vec<RolePolicy>) -> Self {
+    pub fn new(
+        name: impl Into<String>,
+        policies: Vec<RolePolicy>,
+        identifiers: &Identifiers,
+        sensitive_outputs: &SensitiveOutputs,
+    ) -> Self {
+        let name = name.into();
+        identifiers.register("role", &[&name]);
+
         Self {
-            name: name.into(),
+            name,
             policies,
+            identifiers: identifiers.clone(),
+            sensitive_outputs: sensitive_outputs.clone(),
         }
     }
 
     pub fn unique_key(&self) -> String {
-        let mut hasher = DefaultHasher::default();
-        self.name.hash(&mut hasher);
-        hasher.finish().to_string()
+        self.identifiers.key("role", &[&self.name])
     }
 
     pub fn tf_identifier(&self) -> String {
@@ -102,32 +115,43 @@ impl Role {
                     RoleService::EC2 => "ec2.amazonaws.com",
                 };
 
-                format!(
-                    r#"{{
-    "Action" = "{action}"
-    "Effect" = "Allow"
-    "Principal" = {{
-        "Service" = "{service}"
-    }}
-}}"#,
-                )
+                let effect = match p.effect {
+                    RoleEffect::Allow => "Allow",
+                };
+
+                TfField::object(vec![
+                    ("Action", TfField::String(action.into())),
+                    ("Effect", TfField::String(effect.into())),
+                    (
+                        "Principal",
+                        TfField::object(vec![("Service", TfField::String(service.into()))]),
+                    ),
+                ])
             })
-            .collect::<Vec<String>>()
-            .join(",\n\t\t\t");
-
-        let assume_role_policy = format!(
-            r#"jsonencode({{
-        "Version": "2012-10-17",
-        "Statement": [
-            {statement}
-        ]
-    }})"#
+            .collect::<Vec<TfField>>();
+
+        let assume_role_policy = TfField::call(
+            "jsonencode",
+            vec![TfField::object(vec![
+                ("Version", TfField::String("2012-10-17".into())),
+                ("Statement", TfField::List(statement)),
+            ])],
         );
 
         tf_res
             .add_field("name", TfField::String(self.name.clone()))
-            .add_field("assume_role_policy", TfField::Raw(assume_role_policy));
+            .add_field("assume_role_policy", assume_role_policy);
+
+        tf_res.create_terraform().combine(
+            &TfOutput::of(self.var("arn"))
+                .sensitive_if_in(&self.sensitive_outputs)
+                .create_terraform(),
+        )
+    }
+}
 
-        tf_res.create_terraform()
+impl StackEntity for Role {
+    fn create_terraform(&self) -> Terraform {
+        Role::create_terraform(self)
     }
 }
@@ -1,15 +1,18 @@
 use std::{
-    collections::HashMap,
-    fs::{self, File},
-    hash::{DefaultHasher, Hash, Hasher},
-    io::Write,
+    collections::BTreeMap,
+    fs,
+    io::{Cursor, Write},
 };
 
-use zip::{write::SimpleFileOptions, ZipWriter};
+use anyhow::Context;
+use zip::{write::SimpleFileOptions, DateTime, ZipWriter};
 
 use super::{
     iam::role::Role,
-    tf::{Terraform, TerraformEntity, TfField, TfResource, TfVar},
+    tf::{
+        identifier::Identifiers, SensitiveOutputs, Terraform, TerraformEntity, TfBody, TfField,
+        TfOutput, TfResource, TfVar,
+    },
     Shared,
 };
 
@@ -20,10 +23,15 @@ pub enum LambdaRuntime {
 
 #[derive(Clone, Debug)]
 pub struct LambdaFunction {
+    /// What the function is named after, e.g. its API + endpoint. Kept apart so functions whose
+    /// joined names are the same (`main_x` + `y` and `main` + `x_y`) can still be told apart.
+    pub name: Vec<String>,
     pub role: Shared<Role>,
     pub runtime: LambdaRuntime,
     pub file_path: String,
-    pub environment_variables: HashMap<String, String>,
+    pub environment_variables: BTreeMap<String, String>,
+    pub identifiers: Identifiers,
+    pub sensitive_outputs: SensitiveOutputs,
 }
 
 impl TerraformEntity for LambdaFunction {
@@ -50,29 +58,37 @@ impl TerraformEntity for LambdaFunction {
 
 impl LambdaFunction {
     pub fn unique_key(&self) -> String {
-        let mut hasher = DefaultHasher::default();
-        self.file_path.hash(&mut hasher);
-        hasher.finish().to_string()
+        self.identifiers.key("lambda", &self.name_parts())
+    }
+
+    /// Records this function's name, so it's told apart from others with the same [`slug`]
+    ///
+    /// [`slug`]: crate::stack::tf::identifier::slug
+    pub fn register_identifier(&self) {
+        self.identifiers.register("lambda", &self.name_parts());
+    }
+
+    fn name_parts(&self) -> Vec<&str> {
+        self.name.iter().map(String::as_str).collect()
     }
 
     pub fn zip_path(&self) -> String {
         format!("lambda_function_{}.zip", self.unique_key())
     }
 
-    pub fn zip_file(&self, path: &str) -> std::io::Result<()> {
-        let file_buf = File::create(&format!("{path}/{}", self.zip_path()))?;
-
-        let mut zw = ZipWriter::new(file_buf);
-        zw.start_file("index.js", SimpleFileOptions::default())?;
+    /// The zip file uploaded as this function's code. The contents don't depend on when it was
+    /// built, so an unchanged handler zips to the same bytes.
+    pub fn zip(&self) -> anyhow::Result<Vec<u8>> {
+        let mut zw = ZipWriter::new(Cursor::new(vec![]));
+        let options = SimpleFileOptions::default().last_modified_time(DateTime::default());
+        zw.start_file("index.js", options)?;
 
         let fs_conents = fs::read(&self.file_path)
-            .unwrap_or_else(|_| panic!("Unable to read file {} - is it there?", self.file_path));
+            .with_context(|| format!("Unable to read file {} - is it there?", self.file_path))?;
 
         zw.write_all(&fs_conents)?;
 
-        zw.finish()?;
-
-        Ok(())
+        Ok(zw.finish()?.into_inner())
     }
 
     pub fn create_terraform(&self) -> Terraform {
@@ -102,7 +118,7 @@ impl LambdaFunction {
             .environment_variables
             .iter()
             .map(|(key, val)| (key.to_owned(), TfField::String(val.into())))
-            .collect::<HashMap<String, TfField>>();
+            .collect::<Vec<(String, TfField)>>();
 
         let mut lambda_resource =
             TfResource::new_resource("aws_lambda_function", self.tf_identifier());
@@ -115,10 +131,27 @@ impl LambdaFunction {
             .add_field("runtime", TfField::String(runtime))
             .add_field(
                 "source_code_hash",
-                TfField::Raw(format!("filebase64sha256(\"{}\")", self.zip_path())),
-            )
-            .add_field("environment", TfField::Map(environment_vars));
+                TfField::call("filebase64sha256", vec![TfField::String(self.zip_path())]),
+            );
 
-        lambda_resource.create_terraform()
+        if !environment_vars.is_empty() {
+            let mut environment = TfBody::new();
+            environment.set_attribute("variables", TfField::Object(environment_vars));
+
+            lambda_resource.add_block("environment", environment);
+        }
+
+        lambda_resource
+            .create_terraform()
+            .combine(
+                &TfOutput::of(self.var("function_name"))
+                    .sensitive_if_in(&self.sensitive_outputs)
+                    .create_terraform(),
+            )
+            .combine(
+                &TfOutput::of(self.var("arn"))
+                    .sensitive_if_in(&self.sensitive_outputs)
+                    .create_terraform(),
+            )
     }
 }
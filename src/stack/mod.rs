@@ -4,6 +4,9 @@ pub mod api;
 pub mod iam;
 pub mod lambda;
 pub mod region;
+pub mod registry;
+pub mod settings;
+pub mod state;
 pub mod tf;
 
 pub type Shared<T> = Rc<RefCell<T>>;
@@ -1,6 +1,9 @@
-use super::tf::Terraform;
+use super::{
+    registry::StackEntity,
+    tf::{Terraform, TfBlock, TfBody, TfField},
+};
 
-#[derive(Default)]
+#[derive(Default, Debug, Clone)]
 pub enum Region {
     #[default]
     UsEast1,
@@ -14,17 +17,25 @@ impl Region {
     }
 }
 
+#[derive(Default, Debug, Clone)]
 pub struct Provider {
     pub region: Region,
 }
 
 impl Provider {
     pub fn create_terraform(&self) -> Terraform {
-        Terraform(format!(
-            r#"provider "aws" {{
-    region = "{}"
-}}"#,
-            self.region.to_tf_string()
-        ))
+        let mut body = TfBody::new();
+        body.set_attribute(
+            "region",
+            TfField::String(self.region.to_tf_string().to_owned()),
+        );
+
+        TfBlock::new("provider", vec!["aws".into()], body).into()
+    }
+}
+
+impl StackEntity for Provider {
+    fn create_terraform(&self) -> Terraform {
+        Provider::create_terraform(self)
     }
 }
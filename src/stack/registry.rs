@@ -0,0 +1,227 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashSet},
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+use super::{
+    tf::{
+        identifier::{check_unique_identifiers, IdentifierCollision},
+        Terraform, TfAddress,
+    },
+    Shared,
+};
+
+/// Something that can be added to a [`Stack`]
+pub trait StackEntity {
+    /// Adds anything this entity refers to (e.g. a gateway's domain) to the stack, so it doesn't
+    /// have to be added by hand
+    fn register_dependencies(&self, _stack: &mut Stack) {}
+
+    fn create_terraform(&self) -> Terraform;
+}
+
+impl<T: StackEntity> StackEntity for RefCell<T> {
+    fn register_dependencies(&self, stack: &mut Stack) {
+        self.borrow().register_dependencies(stack);
+    }
+
+    fn create_terraform(&self) -> Terraform {
+        self.borrow().create_terraform()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum StackError {
+    #[error(transparent)]
+    DuplicateIdentifiers(#[from] IdentifierCollision),
+    #[error("Resources refer to resources that aren't part of the stack: {}", .0.iter().map(|(from, to)| format!("{from} -> {to}")).collect::<Vec<_>>().join(", "))]
+    MissingReferences(Vec<(String, TfAddress)>),
+}
+
+/// Everything that makes up a generated terraform stack.
+///
+/// Entities are rendered once each, in the order they were added (after their dependencies).
+/// A [`Shared`] entity can be added any number of times.
+#[derive(Default)]
+pub struct Stack {
+    entities: Vec<Rc<dyn StackEntity>>,
+    /// Addresses of the shared entities already added
+    shared: HashSet<*const ()>,
+}
+
+impl Stack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<T: StackEntity + 'static>(&mut self, entity: T) -> &mut Self {
+        entity.register_dependencies(self);
+        self.entities.push(Rc::new(entity));
+
+        self
+    }
+
+    /// Adds an entity shared between other entities (e.g. the role every lambda runs as), unless
+    /// it's already been added
+    pub fn add_shared<T: StackEntity + 'static>(&mut self, entity: &Shared<T>) -> &mut Self {
+        if !self.shared.insert(Rc::as_ptr(entity) as *const ()) {
+            return self;
+        }
+
+        entity.register_dependencies(self);
+        self.entities.push(entity.clone());
+
+        self
+    }
+
+    /// Renders every entity, making sure no two resources share an address and every resource
+    /// referred to is part of the stack
+    pub fn render(&self) -> Result<Terraform, StackError> {
+        let terraform = self.entities.iter().fold(Terraform::default(), |tf, x| {
+            tf.combine(&x.create_terraform())
+        });
+
+        check_unique_identifiers(&terraform)?;
+
+        let declared = terraform
+            .blocks
+            .iter()
+            .filter_map(|x| x.address())
+            .collect::<BTreeSet<_>>();
+
+        let mut missing = vec![];
+        for block in terraform.blocks.iter() {
+            let from = match block.address() {
+                Some(address) => address.to_string(),
+                None => format!("{} {}", block.block_type, block.labels.join(".")),
+            };
+
+            for reference in block.body.references() {
+                if !declared.contains(&reference) {
+                    missing.push((from.clone(), reference));
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            missing.dedup();
+            return Err(StackError::MissingReferences(missing));
+        }
+
+        Ok(terraform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stack::{
+        shared,
+        tf::{TfField, TfResource, TfVar},
+    };
+
+    struct Role(&'static str);
+
+    impl StackEntity for Role {
+        fn create_terraform(&self) -> Terraform {
+            TfResource::new_resource("aws_iam_role", self.0).create_terraform()
+        }
+    }
+
+    /// A lambda running as `role`, which is only added to the stack with it if `shared`
+    struct Lambda {
+        name: &'static str,
+        role: &'static str,
+        shared: Option<Shared<Role>>,
+    }
+
+    impl Lambda {
+        fn new(name: &'static str, role: &Shared<Role>) -> Self {
+            Self {
+                name,
+                role: role.borrow().0,
+                shared: Some(role.clone()),
+            }
+        }
+    }
+
+    impl StackEntity for Lambda {
+        fn register_dependencies(&self, stack: &mut Stack) {
+            if let Some(role) = &self.shared {
+                stack.add_shared(role);
+            }
+        }
+
+        fn create_terraform(&self) -> Terraform {
+            TfResource::new_resource("aws_lambda_function", self.name)
+                .add_field(
+                    "role",
+                    TfField::Variable(TfVar::Resource {
+                        resource_name: "aws_iam_role".into(),
+                        resource_identifier: self.role.into(),
+                        field: "arn".into(),
+                    }),
+                )
+                .create_terraform()
+        }
+    }
+
+    fn addresses(terraform: &Terraform) -> Vec<String> {
+        terraform
+            .blocks
+            .iter()
+            .filter_map(|x| x.address())
+            .map(|x| x.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn renders_shared_entities_once_before_their_dependents() {
+        let role = shared(Role("role"));
+        let mut stack = Stack::new();
+        stack
+            .add(Lambda::new("a", &role))
+            .add(Lambda::new("b", &role))
+            .add_shared(&role);
+
+        assert_eq!(
+            addresses(&stack.render().unwrap()),
+            vec![
+                "aws_iam_role.role",
+                "aws_lambda_function.a",
+                "aws_lambda_function.b"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_references() {
+        let mut stack = Stack::new();
+        stack.add(Lambda {
+            name: "a",
+            role: "role",
+            shared: None,
+        });
+
+        let error = stack.render().unwrap_err();
+        assert!(matches!(error, StackError::MissingReferences(_)));
+        assert_eq!(
+            error.to_string(),
+            "Resources refer to resources that aren't part of the stack: aws_lambda_function.a -> aws_iam_role.role"
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_identifiers() {
+        let mut stack = Stack::new();
+        stack.add(Role("role")).add(Role("role"));
+
+        assert!(matches!(
+            stack.render(),
+            Err(StackError::DuplicateIdentifiers(_))
+        ));
+    }
+}
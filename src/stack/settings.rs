@@ -0,0 +1,100 @@
+use super::{
+    registry::StackEntity,
+    tf::{Terraform, TfBlock, TfBody, TfField},
+};
+
+/// Where terraform keeps its state
+#[derive(Debug, Clone)]
+pub enum Backend {
+    Local {
+        path: Option<String>,
+    },
+    /// State in an S3 bucket, optionally locked with a DynamoDB table so two people can't apply at
+    /// the same time
+    S3 {
+        bucket: String,
+        key: String,
+        region: String,
+        dynamodb_table: Option<String>,
+    },
+}
+
+impl Backend {
+    fn to_block(&self) -> TfBlock {
+        let mut body = TfBody::new();
+
+        let backend_type = match self {
+            Self::Local { path } => {
+                if let Some(path) = path {
+                    body.set_attribute("path", TfField::String(path.clone()));
+                }
+                "local"
+            }
+            Self::S3 {
+                bucket,
+                key,
+                region,
+                dynamodb_table,
+            } => {
+                body.set_attribute("bucket", TfField::String(bucket.clone()))
+                    .set_attribute("key", TfField::String(key.clone()))
+                    .set_attribute("region", TfField::String(region.clone()))
+                    .set_attribute("encrypt", TfField::Bool(true));
+                if let Some(table) = dynamodb_table {
+                    body.set_attribute("dynamodb_table", TfField::String(table.clone()));
+                }
+                "s3"
+            }
+        };
+
+        TfBlock::new("backend", vec![backend_type.into()], body)
+    }
+}
+
+/// The `terraform {}` block - which versions of terraform + the AWS provider to use, and where
+/// state is kept
+#[derive(Debug, Clone)]
+pub struct TerraformSettings {
+    pub required_version: String,
+    pub aws_provider_version: String,
+    pub backend: Option<Backend>,
+}
+
+impl TerraformSettings {
+    pub fn create_terraform(&self) -> Terraform {
+        let mut required_providers = TfBody::new();
+        required_providers.set_attribute(
+            "aws",
+            TfField::object(vec![
+                ("source", TfField::String("hashicorp/aws".into())),
+                (
+                    "version",
+                    TfField::String(self.aws_provider_version.clone()),
+                ),
+            ]),
+        );
+
+        let mut body = TfBody::new();
+        body.set_attribute(
+            "required_version",
+            TfField::String(self.required_version.clone()),
+        )
+        .add_block(TfBlock::new(
+            "required_providers",
+            vec![],
+            required_providers,
+        ));
+
+        if let Some(backend) = &self.backend {
+            body.add_block(backend.to_block());
+        }
+
+        TfBlock::new("terraform", vec![], body).into()
+    }
+}
+
+impl StackEntity for TerraformSettings {
+    fn create_terraform(&self) -> Terraform {
+        TerraformSettings::create_terraform(self)
+    }
+}
@@ -0,0 +1,125 @@
+use super::tf::{
+    Terraform, TerraformEntity, TfBlock, TfBody, TfDataType, TfField, TfOutput, TfResource,
+};
+
+/// An S3 bucket holding terraform state - versioned so a bad apply can be rolled back, encrypted,
+/// and never public
+#[derive(Debug, Clone)]
+pub struct StateBucket {
+    pub name: String,
+}
+
+impl TerraformEntity for StateBucket {
+    fn tf_identifier(&self) -> String {
+        "state".into()
+    }
+
+    fn tf_type() -> &'static str {
+        "aws_s3_bucket"
+    }
+
+    fn data_type() -> TfDataType {
+        TfDataType::Resource
+    }
+}
+
+impl StateBucket {
+    pub fn create_terraform(&self) -> Terraform {
+        let bucket_id = || TfField::Variable(self.var("id"));
+
+        // Losing the state means terraform loses track of everything it made
+        let mut lifecycle = TfBody::new();
+        lifecycle.set_attribute("prevent_destroy", TfField::Bool(true));
+
+        let bucket = TfResource::new_resource(Self::tf_type(), self.tf_identifier())
+            .add_field("bucket", TfField::String(self.name.clone()))
+            .add_block("lifecycle", lifecycle)
+            .create_terraform();
+
+        let mut versioning_configuration = TfBody::new();
+        versioning_configuration.set_attribute("status", TfField::String("Enabled".into()));
+
+        let versioning = TfResource::new_resource("aws_s3_bucket_versioning", self.tf_identifier())
+            .add_field("bucket", bucket_id())
+            .add_block("versioning_configuration", versioning_configuration)
+            .create_terraform();
+
+        let mut encryption_default = TfBody::new();
+        encryption_default.set_attribute("sse_algorithm", TfField::String("AES256".into()));
+        let mut encryption_rule = TfBody::new();
+        encryption_rule.add_block(TfBlock::new(
+            "apply_server_side_encryption_by_default",
+            vec![],
+            encryption_default,
+        ));
+
+        let encryption = TfResource::new_resource(
+            "aws_s3_bucket_server_side_encryption_configuration",
+            self.tf_identifier(),
+        )
+        .add_field("bucket", bucket_id())
+        .add_block("rule", encryption_rule)
+        .create_terraform();
+
+        let public_access_block =
+            TfResource::new_resource("aws_s3_bucket_public_access_block", self.tf_identifier())
+                .add_field("bucket", bucket_id())
+                .add_field("block_public_acls", TfField::Bool(true))
+                .add_field("block_public_policy", TfField::Bool(true))
+                .add_field("ignore_public_acls", TfField::Bool(true))
+                .add_field("restrict_public_buckets", TfField::Bool(true))
+                .create_terraform();
+
+        bucket
+            .combine(&versioning)
+            .combine(&encryption)
+            .combine(&public_access_block)
+            .combine(
+                &TfOutput::new("state_bucket", TfField::Variable(self.var("bucket")))
+                    .description("Bucket to use as the project's S3 backend")
+                    .create_terraform(),
+            )
+    }
+}
+
+/// A DynamoDB table terraform uses to lock the state, so two applies can't run at once
+#[derive(Debug, Clone)]
+pub struct LockTable {
+    pub name: String,
+}
+
+impl TerraformEntity for LockTable {
+    fn tf_identifier(&self) -> String {
+        "state_lock".into()
+    }
+
+    fn tf_type() -> &'static str {
+        "aws_dynamodb_table"
+    }
+
+    fn data_type() -> TfDataType {
+        TfDataType::Resource
+    }
+}
+
+impl LockTable {
+    pub fn create_terraform(&self) -> Terraform {
+        // Terraform's S3 backend expects the key to be a string called LockID
+        let mut attribute = TfBody::new();
+        attribute
+            .set_attribute("name", TfField::String("LockID".into()))
+            .set_attribute("type", TfField::String("S".into()));
+
+        TfResource::new_resource(Self::tf_type(), self.tf_identifier())
+            .add_field("name", TfField::String(self.name.clone()))
+            .add_field("billing_mode", TfField::String("PAY_PER_REQUEST".into()))
+            .add_field("hash_key", TfField::String("LockID".into()))
+            .add_block("attribute", attribute)
+            .create_terraform()
+            .combine(
+                &TfOutput::new("state_lock_table", TfField::Variable(self.var("name")))
+                    .description("Table to use as the S3 backend's dynamodb_table")
+                    .create_terraform(),
+            )
+    }
+}
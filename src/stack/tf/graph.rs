@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use super::{Terraform, TfAddress, TfDataType, TfField};
+
+/// The resources + data sources in some terraform, and which of them refer to which
+#[derive(Debug, Clone, Default)]
+pub struct TfGraph {
+    pub nodes: Vec<TfAddress>,
+    /// `(from, to)` - `from` refers to `to`, so `to` is created first
+    pub edges: BTreeMap<(TfAddress, TfAddress), EdgeKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Through a value, e.g. `aws_iam_role.role_123.arn`
+    Reference,
+    /// Through `depends_on`
+    DependsOn,
+}
+
+impl Terraform {
+    pub fn graph(&self) -> TfGraph {
+        let mut graph = TfGraph::default();
+
+        for block in self.blocks.iter() {
+            let Some(from) = block.address() else {
+                continue;
+            };
+
+            let depends_on = block
+                .body
+                .attribute("depends_on")
+                .map(TfField::references)
+                .unwrap_or_default();
+            for to in depends_on {
+                graph.edges.insert((from.clone(), to), EdgeKind::DependsOn);
+            }
+
+            let references = block
+                .body
+                .attributes()
+                .filter(|(name, _)| *name != "depends_on")
+                .flat_map(|(_, value)| value.references())
+                .chain(block.body.blocks().flat_map(|x| x.body.references()));
+            // An explicit dependency is kept as one, even if it's also referred to elsewhere
+            for to in references {
+                graph
+                    .edges
+                    .entry((from.clone(), to))
+                    .or_insert(EdgeKind::Reference);
+            }
+
+            graph.nodes.push(from);
+        }
+
+        graph
+    }
+}
+
+impl TfGraph {
+    /// A Graphviz graph, e.g. for `dot -Tsvg`
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph terraform {\n  rankdir = LR;\n  node [shape = box];\n");
+
+        for node in self.nodes.iter() {
+            out.push_str(&format!(
+                "  \"{node}\" [label = \"{}\\n{}\"];\n",
+                node_type(node),
+                node.identifier
+            ));
+        }
+
+        if !self.edges.is_empty() {
+            out.push('\n');
+        }
+        for ((from, to), kind) in self.edges.iter() {
+            let style = match kind {
+                EdgeKind::Reference => "",
+                EdgeKind::DependsOn => " [style = dashed]",
+            };
+            out.push_str(&format!("  \"{from}\" -> \"{to}\"{style};\n"));
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// A Mermaid flowchart, which renders in markdown on GitHub + most docs sites
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+
+        // Mermaid ids can't contain `.`, so nodes are numbered
+        let ids = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node, format!("n{i}")))
+            .collect::<BTreeMap<_, _>>();
+
+        for node in self.nodes.iter() {
+            out.push_str(&format!(
+                "  {}[\"{}<br/>{}\"]\n",
+                ids[node],
+                node_type(node),
+                node.identifier
+            ));
+        }
+
+        for ((from, to), kind) in self.edges.iter() {
+            // References to things outside this terraform have nothing to point at
+            let (Some(from), Some(to)) = (ids.get(from), ids.get(to)) else {
+                continue;
+            };
+            let arrow = match kind {
+                EdgeKind::Reference => "-->",
+                EdgeKind::DependsOn => "-.->",
+            };
+            out.push_str(&format!("  {from} {arrow} {to}\n"));
+        }
+
+        out
+    }
+}
+
+/// `aws_iam_role`, or `data.aws_acm_certificate` for a data source
+fn node_type(address: &TfAddress) -> String {
+    match address.data_type {
+        TfDataType::Resource => address.tf_type.clone(),
+        TfDataType::Data => format!("data.{}", address.tf_type),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stack::tf::{TfResource, TfVar};
+
+    fn role_var(field: &str) -> TfField {
+        TfField::Variable(TfVar::Resource {
+            resource_name: "aws_iam_role".into(),
+            resource_identifier: "role".into(),
+            field: field.into(),
+        })
+    }
+
+    /// A role, and a lambda that uses it + depends on it
+    fn terraform() -> Terraform {
+        let role = TfResource::new_resource("aws_iam_role", "role");
+
+        role.create_terraform().combine(
+            &TfResource::new_resource("aws_lambda_function", "lambda")
+                .add_field("role", role_var("arn"))
+                .add_field("depends_on", TfField::List(vec![role.address().into()]))
+                .create_terraform(),
+        )
+    }
+
+    #[test]
+    fn keeps_depends_on_edges_that_are_also_references() {
+        let graph = terraform().graph();
+
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(
+            graph.edges.values().collect::<Vec<_>>(),
+            vec![&EdgeKind::DependsOn]
+        );
+    }
+
+    #[test]
+    fn styles_depends_on_edges() {
+        let graph = terraform().graph();
+
+        assert!(graph.to_dot().contains(
+            "  \"aws_lambda_function.lambda\" -> \"aws_iam_role.role\" [style = dashed];\n"
+        ));
+        assert!(graph.to_mermaid().contains("  n1 -.-> n0\n"));
+    }
+
+    #[test]
+    fn styles_references() {
+        let graph = TfResource::new_resource("aws_iam_role", "role")
+            .create_terraform()
+            .combine(
+                &TfResource::new_resource("aws_lambda_function", "lambda")
+                    .add_field("role", role_var("arn"))
+                    .create_terraform(),
+            )
+            .graph();
+
+        assert!(graph
+            .to_dot()
+            .contains("  \"aws_lambda_function.lambda\" -> \"aws_iam_role.role\";\n"));
+        assert!(graph.to_mermaid().contains("  n1 --> n0\n"));
+    }
+}
@@ -0,0 +1,608 @@
+use std::fmt::Display;
+
+use super::{TfAddress, TfDataType, TfField, TfTemplatePart};
+
+/// `terraform fmt` indents with two spaces
+const INDENT: &str = "  ";
+
+/// A terraform file, made up of top-level blocks (`provider`, `resource`, `data`, ...)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Terraform {
+    pub blocks: Vec<TfBlock>,
+    /// What each resource is for, independent of its identifier (e.g. the route + handler file of
+    /// a lambda). Not written out - it's used to recognise resources that were renamed.
+    pub logical_keys: Vec<(TfAddress, Vec<String>)>,
+    /// The file each resource is written to when the terraform is split up by service, for
+    /// resources that don't have an obvious one (e.g. `api_main` for a gateway's resources)
+    pub files: Vec<(TfAddress, String)>,
+}
+
+impl From<TfBlock> for Terraform {
+    fn from(value: TfBlock) -> Self {
+        Self {
+            blocks: vec![value],
+            logical_keys: vec![],
+            files: vec![],
+        }
+    }
+}
+
+impl Terraform {
+    pub fn combine(&self, other: &Terraform) -> Terraform {
+        let mut result = self.clone();
+        result.blocks.extend(other.blocks.iter().cloned());
+        result
+            .logical_keys
+            .extend(other.logical_keys.iter().cloned());
+        result.files.extend(other.files.iter().cloned());
+        result
+    }
+
+    /// Records `keys` as the logical keys of every resource in this terraform
+    pub fn identified_by(mut self, keys: &[String]) -> Self {
+        for address in self.blocks.iter().filter_map(|x| x.address()) {
+            if address.data_type == TfDataType::Resource {
+                self.logical_keys.push((address, keys.to_vec()));
+            }
+        }
+
+        self
+    }
+
+    pub fn logical_keys(&self, address: &TfAddress) -> Vec<String> {
+        self.logical_keys
+            .iter()
+            .filter(|(x, _)| x == address)
+            .flat_map(|(_, keys)| keys.iter().cloned())
+            .collect()
+    }
+
+    /// Puts every resource + data source in this terraform that isn't in a file yet in `file`
+    pub fn in_file(mut self, file: &str) -> Self {
+        for address in self.blocks.iter().filter_map(|x| x.address()) {
+            if self.file_of(&address).is_none() {
+                self.files.push((address, file.to_owned()));
+            }
+        }
+
+        self
+    }
+
+    pub fn file_of(&self, address: &TfAddress) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|(x, _)| x == address)
+            .map(|(_, file)| file.as_str())
+    }
+
+    pub fn push(&mut self, block: TfBlock) {
+        self.blocks.push(block);
+    }
+
+    /// Finds a top-level block by its type and labels, e.g. `("resource", ["aws_iam_role", "role_123"])`
+    pub fn find_block(&self, block_type: &str, labels: &[&str]) -> Option<&TfBlock> {
+        self.blocks
+            .iter()
+            .find(|x| x.block_type == block_type && x.labels.iter().eq(labels.iter()))
+    }
+}
+
+impl Display for Terraform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut result = String::new();
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            if i != 0 {
+                result.push('\n');
+            }
+            block.write_hcl(&mut result, 0);
+        }
+
+        f.write_str(&result)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TfBodyItem {
+    /// `name = value`
+    Attribute(String, TfField),
+    /// `block_type "label" { ... }`
+    Block(TfBlock),
+}
+
+/// The contents of a block - its attributes and nested blocks, in order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TfBody {
+    pub items: Vec<TfBodyItem>,
+}
+
+impl TfBody {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets an attribute, replacing its value if it's already set
+    pub fn set_attribute(&mut self, name: &str, value: TfField) -> &mut Self {
+        match self.attribute_mut(name) {
+            Some(existing) => *existing = value,
+            None => self
+                .items
+                .push(TfBodyItem::Attribute(name.to_owned(), value)),
+        }
+
+        self
+    }
+
+    pub fn add_block(&mut self, block: TfBlock) -> &mut Self {
+        self.items.push(TfBodyItem::Block(block));
+
+        self
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&TfField> {
+        self.attributes().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    pub fn attribute_mut(&mut self, name: &str) -> Option<&mut TfField> {
+        self.items.iter_mut().find_map(|x| match x {
+            TfBodyItem::Attribute(n, v) if n == name => Some(v),
+            _ => None,
+        })
+    }
+
+    pub fn attributes(&self) -> impl Iterator<Item = (&str, &TfField)> {
+        self.items.iter().filter_map(|x| match x {
+            TfBodyItem::Attribute(n, v) => Some((n.as_str(), v)),
+            TfBodyItem::Block(_) => None,
+        })
+    }
+
+    /// Every resource or data source referred to in this body, including in nested blocks
+    pub fn references(&self) -> Vec<TfAddress> {
+        self.items
+            .iter()
+            .flat_map(|x| match x {
+                TfBodyItem::Attribute(_, value) => value.references(),
+                TfBodyItem::Block(block) => block.body.references(),
+            })
+            .collect()
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &TfBlock> {
+        self.items.iter().filter_map(|x| match x {
+            TfBodyItem::Block(b) => Some(b),
+            TfBodyItem::Attribute(..) => None,
+        })
+    }
+
+    /// Writes the body in the order Terraform's style guide asks for (see [`Placement`]), with a
+    /// blank line between each group
+    fn write_hcl(&self, out: &mut String, indent: usize) {
+        let mut items = self.items.iter().collect::<Vec<_>>();
+        items.sort_by_key(|x| x.placement());
+
+        let mut lines = vec![];
+        for (i, item) in items.iter().enumerate() {
+            if i != 0 && items[i - 1].placement() != item.placement() {
+                lines.push(Line::Blank);
+            }
+            lines.push(match item {
+                TfBodyItem::Attribute(name, value) => Line::Attribute(name.clone(), value),
+                TfBodyItem::Block(block) => Line::Block(block),
+            });
+        }
+
+        write_lines(&lines, out, indent);
+    }
+}
+
+impl TfBodyItem {
+    fn placement(&self) -> Placement {
+        match self {
+            Self::Attribute(name, _) => match name.as_str() {
+                "count" | "for_each" | "provider" => Placement::Leading,
+                "depends_on" => Placement::DependsOn,
+                _ => Placement::Attribute,
+            },
+            Self::Block(block) => match block.block_type.as_str() {
+                "lifecycle" => Placement::Lifecycle,
+                _ => Placement::Block,
+            },
+        }
+    }
+}
+
+/// Where an item goes in its block, following
+/// <https://developer.hashicorp.com/terraform/language/style#resource-order>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Placement {
+    /// `count`, `for_each` and `provider`
+    Leading,
+    Attribute,
+    Block,
+    Lifecycle,
+    DependsOn,
+}
+
+/// A line (or for multi-line values, the start of one) in a block body or object
+enum Line<'a> {
+    Blank,
+    Attribute(String, &'a TfField),
+    Block(&'a TfBlock),
+}
+
+/// Writes out the lines of a body or object, lining up the `=` of consecutive attributes the same
+/// way `terraform fmt` does.
+///
+/// An attribute whose value opens a multi-line bracket (e.g. `tags = {`) ends the run of aligned
+/// attributes, as do blank lines and nested blocks. Heredocs don't, since their opening line is
+/// a complete expression as far as `terraform fmt` is concerned.
+fn write_lines(lines: &[Line], out: &mut String, indent: usize) {
+    let widths = lines
+        .iter()
+        .map(|x| match x {
+            Line::Attribute(name, value) if !value.opens_bracket() => Some(name.chars().count()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // Everything in a run is padded to the longest name in it
+    let mut aligned = Vec::with_capacity(lines.len() + 1);
+    for run in widths.split(Option::is_none) {
+        let max = run.iter().flatten().max().copied().unwrap_or(0);
+        aligned.extend(run.iter().map(|_| max));
+        aligned.push(0);
+    }
+
+    for (line, width) in lines.iter().zip(aligned) {
+        match line {
+            Line::Blank => out.push('\n'),
+            Line::Attribute(name, value) => {
+                push_indent(out, indent);
+                out.push_str(name);
+                for _ in name.chars().count()..width {
+                    out.push(' ');
+                }
+                out.push_str(" = ");
+                value.write_hcl(out, indent);
+                out.push('\n');
+            }
+            Line::Block(block) => {
+                push_indent(out, indent);
+                block.write_hcl(out, indent);
+            }
+        }
+    }
+}
+
+/// `block_type "label" "label" { body }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TfBlock {
+    pub block_type: String,
+    pub labels: Vec<String>,
+    pub body: TfBody,
+}
+
+impl TfBlock {
+    pub fn new(block_type: impl Into<String>, labels: Vec<String>, body: TfBody) -> Self {
+        Self {
+            block_type: block_type.into(),
+            labels,
+            body,
+        }
+    }
+
+    /// The address of a `resource` or `data` block
+    pub fn address(&self) -> Option<TfAddress> {
+        let data_type = match self.block_type.as_str() {
+            "resource" => TfDataType::Resource,
+            "data" => TfDataType::Data,
+            _ => return None,
+        };
+
+        match self.labels.as_slice() {
+            [tf_type, identifier] => Some(TfAddress {
+                data_type,
+                tf_type: tf_type.clone(),
+                identifier: identifier.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn write_hcl(&self, out: &mut String, indent: usize) {
+        out.push_str(&self.block_type);
+        for label in self.labels.iter() {
+            out.push_str(&format!(" \"{}\"", escape_string(label)));
+        }
+        out.push_str(" {\n");
+
+        self.body.write_hcl(out, indent + 1);
+
+        push_indent(out, indent);
+        out.push_str("}\n");
+    }
+}
+
+impl TfField {
+    /// If this expression can't be written on a single line
+    fn is_multiline(&self) -> bool {
+        match self {
+            Self::Object(items) => !items.is_empty(),
+            Self::Heredoc { .. } => true,
+            Self::List(items) | Self::FunctionCall { args: items, .. } => {
+                items.iter().any(|x| x.is_multiline())
+            }
+            Self::Null
+            | Self::Bool(_)
+            | Self::Number(_)
+            | Self::String(_)
+            | Self::Template(_)
+            | Self::Variable(_)
+            | Self::Address(_) => false,
+        }
+    }
+
+    /// If the first line of this expression leaves a bracket open, e.g. `jsonencode({`
+    fn opens_bracket(&self) -> bool {
+        self.is_multiline() && !matches!(self, Self::Heredoc { .. })
+    }
+
+    /// Writes this expression, assuming it starts on a line indented `indent` times
+    pub(super) fn write_hcl(&self, out: &mut String, indent: usize) {
+        match self {
+            Self::Null => out.push_str("null"),
+            Self::Bool(b) => out.push_str(&b.to_string()),
+            Self::Number(n) => out.push_str(&n.to_string()),
+            Self::String(s) => out.push_str(&format!("\"{}\"", escape_string(s))),
+            Self::Template(parts) => {
+                out.push('"');
+                for (i, part) in parts.iter().enumerate() {
+                    match part {
+                        TfTemplatePart::Literal(s) => out.push_str(&escape_template(
+                            &escape_characters(s),
+                            parts
+                                .get(i + 1)
+                                .is_some_and(TfTemplatePart::is_interpolation),
+                        )),
+                        TfTemplatePart::Variable(v) => {
+                            out.push_str(&format!("${{{}}}", v.to_tf_string()))
+                        }
+                    }
+                }
+                out.push('"');
+            }
+            Self::Variable(v) => out.push_str(&v.to_tf_string()),
+            Self::Address(a) => out.push_str(&a.to_string()),
+            Self::List(items) => {
+                if self.is_multiline() {
+                    out.push_str("[\n");
+                    for item in items {
+                        push_indent(out, indent + 1);
+                        item.write_hcl(out, indent + 1);
+                        out.push_str(",\n");
+                    }
+                    push_indent(out, indent);
+                    out.push(']');
+                } else {
+                    out.push('[');
+                    for (i, item) in items.iter().enumerate() {
+                        if i != 0 {
+                            out.push_str(", ");
+                        }
+                        item.write_hcl(out, indent);
+                    }
+                    out.push(']');
+                }
+            }
+            Self::Object(items) => {
+                if items.is_empty() {
+                    out.push_str("{}");
+                    return;
+                }
+
+                let lines = items
+                    .iter()
+                    .map(|(key, value)| Line::Attribute(object_key(key), value))
+                    .collect::<Vec<_>>();
+
+                out.push_str("{\n");
+                write_lines(&lines, out, indent + 1);
+                push_indent(out, indent);
+                out.push('}');
+            }
+            Self::FunctionCall { name, args } => {
+                out.push_str(name);
+                out.push('(');
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 {
+                        out.push_str(", ");
+                    }
+                    arg.write_hcl(out, indent);
+                }
+                out.push(')');
+            }
+            Self::Heredoc { marker, content } => {
+                out.push_str(&format!("<<{marker}\n{}", escape_template(content, false)));
+                if !content.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(marker);
+            }
+        }
+    }
+
+    /// Writes this expression on a single line, e.g. `jsonencode({ Version = "2012-10-17" })`, for
+    /// when it has to go inside a `${...}` interpolation
+    pub fn to_inline_hcl(&self) -> String {
+        let mut out = String::new();
+        self.write_inline_hcl(&mut out);
+        out
+    }
+
+    fn write_inline_hcl(&self, out: &mut String) {
+        let write_all = |out: &mut String, items: &[TfField]| {
+            for (i, item) in items.iter().enumerate() {
+                if i != 0 {
+                    out.push_str(", ");
+                }
+                item.write_inline_hcl(out);
+            }
+        };
+
+        match self {
+            Self::List(items) => {
+                out.push('[');
+                write_all(out, items);
+                out.push(']');
+            }
+            Self::Object(items) if items.is_empty() => out.push_str("{}"),
+            Self::Object(items) => {
+                out.push_str("{ ");
+                for (i, (key, value)) in items.iter().enumerate() {
+                    if i != 0 {
+                        out.push_str(", ");
+                    }
+                    out.push_str(&object_key(key));
+                    out.push_str(" = ");
+                    value.write_inline_hcl(out);
+                }
+                out.push_str(" }");
+            }
+            Self::FunctionCall { name, args } => {
+                out.push_str(name);
+                out.push('(');
+                write_all(out, args);
+                out.push(')');
+            }
+            Self::Heredoc { content, .. } => {
+                out.push_str(&format!("\"{}\"", escape_string(content)))
+            }
+            Self::Null
+            | Self::Bool(_)
+            | Self::Number(_)
+            | Self::String(_)
+            | Self::Template(_)
+            | Self::Variable(_)
+            | Self::Address(_) => self.write_hcl(out, 0),
+        }
+    }
+}
+
+/// Object keys that aren't valid identifiers need to be quoted
+fn object_key(key: &str) -> String {
+    let is_identifier = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if is_identifier {
+        key.to_owned()
+    } else {
+        format!("\"{}\"", escape_string(key))
+    }
+}
+
+/// Escapes a string so it can be written between quotes and comes out as exactly the same string
+pub fn escape_string(s: &str) -> String {
+    escape_template(&escape_characters(s), false)
+}
+
+/// Escapes quotes, backslashes and control characters
+fn escape_characters(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04X}", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Stops `${` and `%{` from being read as interpolations or directives.
+///
+/// A `$` or `%` at the end of a literal that's `before_interpolation` is written as `${"$"}`
+/// instead, since `$` + `${x}` would be read as the escaped `$${x}` and lose the interpolation.
+pub(super) fn escape_template(s: &str, before_interpolation: bool) -> String {
+    let escaped = s.replace("${", "$${").replace("%{", "%%{");
+
+    match escaped.strip_suffix(['$', '%']) {
+        Some(rest) if before_interpolation => {
+            format!("{rest}${{\"{}\"}}", &escaped[rest.len()..])
+        }
+        _ => escaped,
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str(INDENT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stack::tf::TfVar;
+
+    fn arn() -> TfVar {
+        TfVar::Resource {
+            resource_name: "aws_iam_role".into(),
+            resource_identifier: "role".into(),
+            field: "arn".into(),
+        }
+    }
+
+    fn hcl(field: &TfField) -> String {
+        let mut out = String::new();
+        field.write_hcl(&mut out, 0);
+        out
+    }
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(escape_string("plain"), "plain");
+        assert_eq!(escape_string("say \"hi\"\n"), "say \\\"hi\\\"\\n");
+        assert_eq!(escape_string("C:\\dir\t"), "C:\\\\dir\\t");
+        assert_eq!(escape_string("\u{7}"), "\\u0007");
+        assert_eq!(escape_string("${var} %{if}"), "$${var} %%{if}");
+        // Only `${` and `%{` start interpolations + directives
+        assert_eq!(escape_string("$5 100% $"), "$5 100% $");
+    }
+
+    #[test]
+    fn renders_templates() {
+        let template = TfField::template(vec!["https://".into(), arn().into(), "/${stage}".into()]);
+
+        assert_eq!(
+            hcl(&template),
+            "\"https://${aws_iam_role.role.arn}/$${stage}\""
+        );
+    }
+
+    #[test]
+    fn keeps_interpolations_after_a_dollar() {
+        let template = TfField::template(vec!["cost: $".into(), arn().into(), "$".into()]);
+        assert_eq!(
+            hcl(&template),
+            "\"cost: ${\"$\"}${aws_iam_role.role.arn}$\""
+        );
+
+        let template = TfField::template(vec!["100%".into(), arn().into()]);
+        assert_eq!(hcl(&template), "\"100${\"%\"}${aws_iam_role.role.arn}\"");
+
+        let template = TfField::template(vec!["a${".into(), arn().into()]);
+        assert_eq!(hcl(&template), "\"a$${${aws_iam_role.role.arn}\"");
+    }
+}
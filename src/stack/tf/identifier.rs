@@ -0,0 +1,287 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+use thiserror::Error;
+
+use super::Terraform;
+
+/// Every name in a stack that's made into a terraform identifier, so names that only collide once
+/// they're made into identifiers (e.g. `get-posts` and `get_posts`) can be told apart.
+///
+/// Names are registered as their entities are created, and then looked up with [`Self::key`] as
+/// they're rendered. It's shared (cheaply cloned) between the entities of one stack.
+#[derive(Clone, Debug, Default)]
+pub struct Identifiers(Rc<RefCell<NamesBySlug>>);
+
+/// The names registered, by kind + [`slug`]
+type NamesBySlug = BTreeMap<(String, String), BTreeSet<Vec<String>>>;
+
+impl Identifiers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a name that will be made into an identifier. `kind` is the prefix the key is used
+    /// with (e.g. `lambda`), as keys only have to be unique among resources of the same kind.
+    pub fn register(&self, kind: &str, parts: &[&str]) {
+        self.0
+            .borrow_mut()
+            .entry((kind.to_owned(), slug(parts)))
+            .or_default()
+            .insert(parts.iter().map(|x| x.to_string()).collect());
+    }
+
+    /// The part of a terraform identifier that tells this resource apart from others of its
+    /// kind - its [`slug`], with a [`stable_hash`] of `parts` added if another registered name of
+    /// the same kind has the same slug.
+    ///
+    /// Names that are exactly the same can't be told apart, so they're left to collide (see
+    /// [`check_unique_identifiers`]).
+    pub fn key(&self, kind: &str, parts: &[&str]) -> String {
+        let key = slug(parts);
+
+        let colliding = self
+            .0
+            .borrow()
+            .get(&(kind.to_owned(), key.clone()))
+            .is_some_and(|names| {
+                names
+                    .iter()
+                    .any(|name| !name.iter().map(String::as_str).eq(parts.iter().copied()))
+            });
+
+        if colliding {
+            format!("{key}_{}", stable_hash(parts))
+        } else {
+            key
+        }
+    }
+}
+
+/// Builds a readable identifier out of names, e.g. `["main", "get-posts"]` becomes
+/// `main_get_posts`.
+///
+/// Names are lowercased (`LambdaRole` becomes `lambda_role`) and `-`, `/`, `.` and spaces become
+/// `_`. If anything else has to be dropped to make a valid identifier, a [`stable_hash`] of the
+/// original parts is added on the end so names that only differ in those characters don't collide.
+///
+/// This only depends on the names passed in, so adding or removing other resources never renames
+/// an existing one - unless two different sets of names end up with the same slug, which
+/// [`Identifiers::key`] tells apart.
+pub fn slug(parts: &[&str]) -> String {
+    let mut key = String::new();
+    let mut lossy = false;
+
+    for part in parts {
+        let mut previous: Option<char> = None;
+        for c in part.chars() {
+            if c.is_ascii_uppercase()
+                && previous.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit())
+            {
+                push_separator(&mut key);
+            }
+
+            match c {
+                c if c.is_ascii_alphanumeric() => key.push(c.to_ascii_lowercase()),
+                '_' | '-' | '/' | '.' | ' ' => push_separator(&mut key),
+                _ => lossy = true,
+            }
+
+            previous = Some(c);
+        }
+
+        push_separator(&mut key);
+    }
+
+    let key = key.trim_matches('_');
+    match (key.is_empty(), lossy) {
+        (true, _) | (false, true) => {
+            let hash = stable_hash(parts);
+            if key.is_empty() {
+                hash
+            } else {
+                format!("{key}_{hash}")
+            }
+        }
+        (false, false) => key.to_owned(),
+    }
+}
+
+fn push_separator(key: &mut String) {
+    if !key.is_empty() && !key.ends_with('_') {
+        key.push('_');
+    }
+}
+
+/// A short hash of `parts` that's the same on every platform + Rust version (64 bit FNV-1a),
+/// unlike [`std::hash::DefaultHasher`]
+pub fn stable_hash(parts: &[&str]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for part in parts {
+        // Separate each part so ["ab", "c"] and ["a", "bc"] hash differently
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    format!("{:08x}", hash as u32 ^ (hash >> 32) as u32)
+}
+
+#[derive(Debug, Error)]
+#[error("Multiple resources would be generated with the same terraform identifier: {}. Rename one of them in the config.", .0.join(", "))]
+pub struct IdentifierCollision(pub Vec<String>);
+
+/// Makes sure no two blocks share an address (e.g. `aws_lambda_function.lambda_main_get_posts`),
+/// which terraform would refuse to load
+pub fn check_unique_identifiers(terraform: &Terraform) -> Result<(), IdentifierCollision> {
+    let mut counts = BTreeMap::new();
+
+    for block in terraform.blocks.iter().filter(|x| !x.labels.is_empty()) {
+        let address = match block.block_type.as_str() {
+            "resource" => block.labels.join("."),
+            block_type => format!("{block_type}.{}", block.labels.join(".")),
+        };
+
+        *counts.entry(address).or_insert(0) += 1;
+    }
+
+    let duplicates = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(address, _)| address)
+        .collect::<Vec<_>>();
+
+    if duplicates.is_empty() {
+        Ok(())
+    } else {
+        Err(IdentifierCollision(duplicates))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stack::tf::TfResource;
+
+    #[test]
+    fn slugs_names() {
+        assert_eq!(slug(&["main", "get-posts"]), "main_get_posts");
+        assert_eq!(slug(&["LambdaRole"]), "lambda_role");
+        assert_eq!(slug(&["api.example.com"]), "api_example_com");
+        assert_eq!(
+            slug(&["main", "/posts//{id}/"]),
+            format!("main_posts_id_{}", stable_hash(&["main", "/posts//{id}/"]))
+        );
+        assert_eq!(slug(&["__a__", " b "]), "a_b");
+    }
+
+    #[test]
+    fn hashes_dropped_characters() {
+        let plain = slug(&["posts"]);
+        let dropped = slug(&["posts!"]);
+        assert_eq!(plain, "posts");
+        assert!(dropped.starts_with("posts_"));
+        assert_ne!(dropped, slug(&["posts?"]));
+        assert_eq!(slug(&["???"]), stable_hash(&["???"]));
+    }
+
+    #[test]
+    fn stable_hash_separates_parts() {
+        assert_eq!(stable_hash(&["ab", "c"]), stable_hash(&["ab", "c"]));
+        assert_ne!(stable_hash(&["ab", "c"]), stable_hash(&["a", "bc"]));
+        assert_eq!(stable_hash(&["main"]).len(), 8);
+    }
+
+    #[test]
+    fn adds_a_hash_to_colliding_keys() {
+        let identifiers = Identifiers::new();
+        identifiers.register("lambda", &["main", "get-posts"]);
+        identifiers.register("lambda", &["main", "get_posts"]);
+        identifiers.register("lambda", &["main", "post_posts"]);
+
+        assert_eq!(
+            identifiers.key("lambda", &["main", "get-posts"]),
+            format!("main_get_posts_{}", stable_hash(&["main", "get-posts"]))
+        );
+        assert_eq!(
+            identifiers.key("lambda", &["main", "get_posts"]),
+            format!("main_get_posts_{}", stable_hash(&["main", "get_posts"]))
+        );
+        // Parts are kept apart, so `main_x` + `y` collides with `main` + `x_y`
+        identifiers.register("lambda", &["main_x", "y"]);
+        identifiers.register("lambda", &["main", "x_y"]);
+        assert_ne!(
+            identifiers.key("lambda", &["main_x", "y"]),
+            identifiers.key("lambda", &["main", "x_y"])
+        );
+
+        // Others keep their keys
+        assert_eq!(
+            identifiers.key("lambda", &["main", "post_posts"]),
+            "main_post_posts"
+        );
+    }
+
+    #[test]
+    fn keys_only_collide_within_a_kind() {
+        let identifiers = Identifiers::new();
+        identifiers.register("gateway", &["main_x"]);
+        identifiers.register("lambda", &["main", "x"]);
+
+        assert_eq!(identifiers.key("gateway", &["main_x"]), "main_x");
+        assert_eq!(identifiers.key("lambda", &["main", "x"]), "main_x");
+    }
+
+    #[test]
+    fn keys_dont_depend_on_lookup_order() {
+        let identifiers = Identifiers::new();
+        let before = identifiers.key("lambda", &["main", "get-posts"]);
+        identifiers.register("lambda", &["main", "get-posts"]);
+        identifiers.register("lambda", &["main", "get_posts"]);
+
+        // Only registered names count, and every lookup after that gives the same key
+        assert_eq!(before, "main_get_posts");
+        let after = identifiers.key("lambda", &["main", "get-posts"]);
+        assert_ne!(after, before);
+        assert_eq!(identifiers.key("lambda", &["main", "get-posts"]), after);
+        assert_eq!(
+            identifiers.clone().key("lambda", &["main", "get-posts"]),
+            after
+        );
+    }
+
+    #[test]
+    fn identical_names_still_collide() {
+        let identifiers = Identifiers::new();
+        identifiers.register("lambda", &["main", "x"]);
+        identifiers.register("lambda", &["main", "x"]);
+
+        let lambda = || {
+            TfResource::new_resource(
+                "aws_lambda_function",
+                format!("lambda_{}", identifiers.key("lambda", &["main", "x"])),
+            )
+            .create_terraform()
+        };
+        let terraform = lambda().combine(&lambda());
+
+        let collision = check_unique_identifiers(&terraform).unwrap_err();
+        assert_eq!(collision.0, vec!["aws_lambda_function.lambda_main_x"]);
+    }
+
+    #[test]
+    fn data_sources_are_told_apart_from_resources() {
+        let terraform = TfResource::new_data("aws_acm_certificate", "certificate")
+            .create_terraform()
+            .combine(
+                &TfResource::new_resource("aws_acm_certificate", "certificate").create_terraform(),
+            );
+
+        assert!(check_unique_identifiers(&terraform).is_ok());
+    }
+}
@@ -0,0 +1,193 @@
+use serde_json::{Map, Value};
+
+use super::{
+    hcl::escape_template, Terraform, TfBlock, TfBody, TfBodyItem, TfField, TfTemplatePart,
+};
+
+/// Attributes whose values terraform reads as bare references (`"aws_x.y"`) rather than as
+/// expressions (`"${aws_x.y}"`) in JSON syntax
+const REFERENCE_ATTRIBUTES: [&str; 4] = ["depends_on", "from", "to", "replace_triggered_by"];
+
+impl Terraform {
+    /// This terraform in terraform's JSON syntax (what goes in a `.tf.json` file).
+    ///
+    /// See <https://developer.hashicorp.com/terraform/language/syntax/json>
+    pub fn to_json(&self) -> Value {
+        let mut root = Map::new();
+
+        for block in self.blocks.iter() {
+            let body = Value::Object(block.body.to_json(&block.block_type));
+
+            match block.labels.split_last() {
+                // Unlabeled blocks (moved, terraform, ...) can be repeated, so they go in a list
+                None => push_to_list(&mut root, &block.block_type, body),
+                Some((last, labels)) => {
+                    let mut parent = object_entry(&mut root, &block.block_type);
+                    for label in labels {
+                        parent = object_entry(parent, label);
+                    }
+                    // e.g. several `provider "aws"` blocks with different aliases
+                    insert_or_push(parent, last, body);
+                }
+            }
+        }
+
+        Value::Object(root)
+    }
+
+    pub fn to_json_string(&self) -> String {
+        let mut json = serde_json::to_string_pretty(&self.to_json())
+            .expect("Terraform JSON can always be serialized");
+        json.push('\n');
+        json
+    }
+}
+
+impl TfBody {
+    fn to_json(&self, block_type: &str) -> Map<String, Value> {
+        let mut result = Map::new();
+
+        for item in self.items.iter() {
+            match item {
+                TfBodyItem::Attribute(name, value) => {
+                    let as_reference =
+                        block_type == "moved" || REFERENCE_ATTRIBUTES.contains(&name.as_str());
+                    result.insert(name.clone(), value.to_json(as_reference));
+                }
+                TfBodyItem::Block(block) => add_nested_block(&mut result, block),
+            }
+        }
+
+        result
+    }
+}
+
+/// Nested blocks are an object, or a list of objects if there's more than one of that type
+fn add_nested_block(body: &mut Map<String, Value>, block: &TfBlock) {
+    let mut value = Value::Object(block.body.to_json(&block.block_type));
+    for label in block.labels.iter().rev() {
+        value = Value::Object(Map::from_iter([(label.clone(), value)]));
+    }
+
+    insert_or_push(body, &block.block_type, value);
+}
+
+/// Sets `key` to `value`, or makes it a list of every value if `key` is already set
+fn insert_or_push(map: &mut Map<String, Value>, key: &str, value: Value) {
+    match map.get_mut(key) {
+        Some(Value::Array(existing)) => existing.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            map.insert(key.to_owned(), value);
+        }
+    }
+}
+
+impl TfField {
+    /// Anything that isn't a plain value becomes a `"${...}"` template. With `as_reference`,
+    /// addresses are written bare as required by `depends_on` and `moved`.
+    fn to_json(&self, as_reference: bool) -> Value {
+        match self {
+            Self::Null => Value::Null,
+            Self::Bool(b) => Value::Bool(*b),
+            Self::Number(n) => serde_json::Number::from_f64(*n)
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+            Self::String(s) | Self::Heredoc { content: s, .. } => {
+                Value::String(escape_template(s, false))
+            }
+            Self::Template(parts) => Value::String(
+                parts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| match x {
+                        TfTemplatePart::Literal(s) => escape_template(
+                            s,
+                            parts
+                                .get(i + 1)
+                                .is_some_and(TfTemplatePart::is_interpolation),
+                        ),
+                        TfTemplatePart::Variable(v) => format!("${{{}}}", v.to_tf_string()),
+                    })
+                    .collect(),
+            ),
+            Self::Variable(v) => Value::String(format!("${{{}}}", v.to_tf_string())),
+            Self::Address(a) if as_reference => Value::String(a.to_string()),
+            Self::Address(a) => Value::String(format!("${{{a}}}")),
+            Self::List(items) => {
+                Value::Array(items.iter().map(|x| x.to_json(as_reference)).collect())
+            }
+            Self::Object(items) => Value::Object(
+                items
+                    .iter()
+                    .map(|(k, v)| (escape_template(k, false), v.to_json(as_reference)))
+                    .collect(),
+            ),
+            Self::FunctionCall { .. } => Value::String(format!("${{{}}}", self.to_inline_hcl())),
+        }
+    }
+}
+
+fn object_entry<'a>(map: &'a mut Map<String, Value>, key: &str) -> &'a mut Map<String, Value> {
+    let value = map.entry(key).or_insert_with(|| Value::Object(Map::new()));
+
+    match value {
+        Value::Object(object) => object,
+        _ => panic!("Expected `{key}` to be an object in the terraform JSON"),
+    }
+}
+
+fn push_to_list(map: &mut Map<String, Value>, key: &str, value: Value) {
+    match map.entry(key).or_insert_with(|| Value::Array(vec![])) {
+        Value::Array(list) => list.push(value),
+        _ => panic!("Expected `{key}` to be a list in the terraform JSON"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stack::tf::TfVar;
+
+    #[test]
+    fn repeats_blocks_with_the_same_labels() {
+        let provider = |region: &str| {
+            let mut body = TfBody::new();
+            body.set_attribute("region", TfField::String(region.into()));
+            TfBlock::new("provider", vec!["aws".into()], body)
+        };
+
+        let mut terraform = Terraform::from(provider("us-east-1"));
+        terraform.push(provider("eu-west-1"));
+
+        assert_eq!(
+            terraform.to_json(),
+            serde_json::json!({
+                "provider": {
+                    "aws": [{ "region": "us-east-1" }, { "region": "eu-west-1" }]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn escapes_templates() {
+        let arn = TfVar::Resource {
+            resource_name: "aws_iam_role".into(),
+            resource_identifier: "role".into(),
+            field: "arn".into(),
+        };
+
+        assert_eq!(
+            TfField::String("${x} %{y}".into()).to_json(false),
+            Value::String("$${x} %%{y}".into())
+        );
+        assert_eq!(
+            TfField::template(vec!["cost: $".into(), arn.into(), "/${x}".into()]).to_json(false),
+            Value::String("cost: ${\"$\"}${aws_iam_role.role.arn}/$${x}".into())
+        );
+    }
+}
@@ -1,17 +1,33 @@
-use std::collections::HashMap;
+use std::{collections::BTreeSet, fmt::Display, rc::Rc};
 
-use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Debug, Clone, Display, Default)]
-pub struct Terraform(pub String);
+pub mod graph;
+pub mod hcl;
+pub mod identifier;
+pub mod json;
 
-impl Terraform {
-    pub fn combine(&self, other: &Terraform) -> Terraform {
-        Self(format!("{}\n{}", self.0, other.0))
+pub use hcl::{escape_string, Terraform, TfBlock, TfBody, TfBodyItem};
+
+/// A resource or data source, e.g. `aws_iam_role.role_123` or `data.aws_acm_certificate.cert_123`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TfAddress {
+    pub data_type: TfDataType,
+    pub tf_type: String,
+    pub identifier: String,
+}
+
+impl Display for TfAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.data_type {
+            TfDataType::Resource => write!(f, "{}.{}", self.tf_type, self.identifier),
+            TfDataType::Data => write!(f, "data.{}.{}", self.tf_type, self.identifier),
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TfVar {
     Resource {
         resource_name: String,
@@ -40,6 +56,30 @@ impl TfVar {
             } => format!("data.{data_name}.{data_identifier}.{field}",),
         }
     }
+
+    /// The resource or data source this variable is read from
+    pub fn address(&self) -> TfAddress {
+        match self {
+            Self::Resource {
+                resource_name,
+                resource_identifier,
+                ..
+            } => TfAddress {
+                data_type: TfDataType::Resource,
+                tf_type: resource_name.clone(),
+                identifier: resource_identifier.clone(),
+            },
+            Self::Data {
+                data_name,
+                data_identifier,
+                ..
+            } => TfAddress {
+                data_type: TfDataType::Data,
+                tf_type: data_name.clone(),
+                identifier: data_identifier.clone(),
+            },
+        }
+    }
 }
 
 impl From<String> for TfField {
@@ -54,63 +94,138 @@ impl From<TfVar> for TfField {
     }
 }
 
-#[derive(Debug, Clone)]
+impl From<TfAddress> for TfField {
+    fn from(value: TfAddress) -> Self {
+        Self::Address(value)
+    }
+}
+
+impl TfTemplatePart {
+    /// Written as `${...}`
+    pub fn is_interpolation(&self) -> bool {
+        matches!(self, Self::Variable(_))
+    }
+}
+
+impl From<&str> for TfTemplatePart {
+    fn from(value: &str) -> Self {
+        Self::Literal(value.to_owned())
+    }
+}
+
+impl From<String> for TfTemplatePart {
+    fn from(value: String) -> Self {
+        Self::Literal(value)
+    }
+}
+
+impl From<TfVar> for TfTemplatePart {
+    fn from(value: TfVar) -> Self {
+        Self::Variable(value)
+    }
+}
+
+impl From<bool> for TfField {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+/// An HCL expression
+#[derive(Debug, Clone, PartialEq)]
 pub enum TfField {
-    Raw(String),
-    Variable(TfVar),
+    Null,
+    Bool(bool),
+    Number(f64),
+    /// A literal string - it's escaped when written, so it can safely contain quotes, newlines, `${`
+    /// and so on
     String(String),
+    /// A string built from literals and interpolated variables, e.g. `"${aws_x.y.arn}/*"`
+    Template(Vec<TfTemplatePart>),
+    /// A field of another resource, e.g. `aws_iam_role.role_123.arn`
+    Variable(TfVar),
+    /// A whole resource, e.g. `aws_iam_role.role_123` in `depends_on`
+    Address(TfAddress),
     List(Vec<TfField>),
-    Map(HashMap<String, TfField>),
-    Object(HashMap<String, TfField>),
+    Object(Vec<(String, TfField)>),
+    FunctionCall {
+        name: String,
+        args: Vec<TfField>,
+    },
+    Heredoc {
+        marker: String,
+        content: String,
+    },
+}
+
+/// A piece of a [`TfField::Template`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum TfTemplatePart {
+    Literal(String),
+    /// Written as `${...}`
+    Variable(TfVar),
 }
 
 impl TfField {
-    pub fn eq_prefix(&self) -> String {
-        match self {
-            Self::Variable(_)
-            | Self::String(_)
-            | Self::List(_)
-            | Self::Raw(_)
-            | Self::Object(_) => "= ",
-            Self::Map(_) => "",
+    pub fn template(parts: Vec<TfTemplatePart>) -> Self {
+        Self::Template(parts)
+    }
+
+    pub fn object(items: Vec<(impl Into<String>, TfField)>) -> Self {
+        Self::Object(items.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    pub fn call(name: impl Into<String>, args: Vec<TfField>) -> Self {
+        Self::FunctionCall {
+            name: name.into(),
+            args,
         }
-        .into()
     }
 
-    pub fn map(items: Vec<(String, TfField)>) -> Self {
-        Self::Map(items.into_iter().collect())
+    /// Every resource or data source this expression refers to
+    pub fn references(&self) -> Vec<TfAddress> {
+        let mut result = vec![];
+        self.collect_references(&mut result);
+        result
     }
 
-    pub fn to_tf_string(&self) -> String {
+    fn collect_references(&self, result: &mut Vec<TfAddress>) {
         match self {
-            Self::Raw(s) => s.clone(),
-            Self::String(s) => format!("\"{}\"", s),
-            Self::Variable(v) => v.to_tf_string(),
-            Self::List(l) => {
-                let list = l
-                    .iter()
-                    .map(|field| field.to_tf_string())
-                    .collect::<Vec<String>>()
-                    .join(", ");
-
-                format!("[{list}]")
+            Self::Variable(v) => result.push(v.address()),
+            Self::Address(a) => result.push(a.clone()),
+            Self::List(items) | Self::FunctionCall { args: items, .. } => {
+                for item in items {
+                    item.collect_references(result);
+                }
+            }
+            Self::Object(items) => {
+                for (_, item) in items {
+                    item.collect_references(result);
+                }
             }
-            Self::Map(m) | Self::Object(m) => {
-                let map = m
-                    .iter()
-                    .map(|(key, field)| {
-                        format!("{key} {}{}", field.eq_prefix(), field.to_tf_string())
-                    })
-                    .collect::<Vec<String>>()
-                    .join("\n\t");
-
-                format!("{{\n\t{map}\n}}")
+            Self::Template(parts) => {
+                for part in parts {
+                    if let TfTemplatePart::Variable(v) = part {
+                        result.push(v.address());
+                    }
+                }
             }
+            Self::Null
+            | Self::Bool(_)
+            | Self::Number(_)
+            | Self::String(_)
+            | Self::Heredoc { .. } => {}
         }
     }
+
+    pub fn to_tf_string(&self) -> String {
+        let mut result = String::new();
+        self.write_hcl(&mut result, 0);
+        result
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum TfDataType {
     Resource,
     Data,
@@ -131,19 +246,118 @@ pub trait TerraformEntity {
             },
         }
     }
+    fn address(&self) -> TfAddress {
+        TfAddress {
+            data_type: Self::data_type(),
+            tf_type: Self::tf_type().into(),
+            identifier: self.tf_identifier(),
+        }
+    }
     fn tf_identifier(&self) -> String;
     fn tf_type() -> &'static str;
     fn data_type() -> TfDataType;
 }
 
-pub struct TfOutput {}
+/// An `output` block, which makes a value available after `terraform apply` (and to
+/// `terraform output`)
+#[derive(Debug, Clone)]
+pub struct TfOutput {
+    name: String,
+    body: TfBody,
+}
+
+impl TfOutput {
+    pub fn new(name: impl Into<String>, value: TfField) -> Self {
+        let mut body = TfBody::new();
+        body.set_attribute("value", value);
+
+        Self {
+            name: name.into(),
+            body,
+        }
+    }
+
+    /// Outputs a field of a resource, named after the resource + field, e.g.
+    /// `lambda_main_get_posts_arn`
+    pub fn of(var: TfVar) -> Self {
+        let (TfVar::Resource {
+            resource_identifier: identifier,
+            field,
+            ..
+        }
+        | TfVar::Data {
+            data_identifier: identifier,
+            field,
+            ..
+        }) = &var;
+
+        Self::new(format!("{identifier}_{field}"), TfField::Variable(var))
+    }
+
+    pub fn description(&mut self, description: impl Into<String>) -> &mut Self {
+        self.body
+            .set_attribute("description", TfField::String(description.into()));
+
+        self
+    }
+
+    /// Hides the value in terraform's plan + apply output
+    pub fn sensitive(&mut self, sensitive: bool) -> &mut Self {
+        self.body
+            .set_attribute("sensitive", TfField::Bool(sensitive));
+
+        self
+    }
+
+    /// Marks this output [`Self::sensitive`] if it's one of `outputs`
+    pub fn sensitive_if_in(&mut self, outputs: &SensitiveOutputs) -> &mut Self {
+        if outputs.contains(&self.name) {
+            self.sensitive(true);
+        }
+
+        self
+    }
+
+    pub fn to_block(&self) -> TfBlock {
+        TfBlock::new("output", vec![self.name.clone()], self.body.clone())
+    }
+
+    pub fn create_terraform(&self) -> Terraform {
+        Terraform::from(self.to_block())
+    }
+}
+
+/// The outputs terraform should hide in its plan + apply output, by name. It's shared (cheaply
+/// cloned) between the entities that create outputs.
+#[derive(Debug, Clone, Default)]
+pub struct SensitiveOutputs(Rc<BTreeSet<String>>);
+
+impl SensitiveOutputs {
+    pub fn new(names: impl IntoIterator<Item = String>) -> Self {
+        Self(Rc::new(names.into_iter().collect()))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.0.iter()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TfResourceError {
+    #[error("Invalid state for depends_on. Expected a list, got: {0:?}")]
+    InvalidDependsOn(TfField),
+}
 
 #[derive(Debug, Clone)]
 pub struct TfResource {
-    tf_type: String,
+    data_type: TfDataType,
     resource_name: String,
     resource_identifier: String,
-    fields: HashMap<String, TfField>,
+    body: TfBody,
 }
 
 impl TfResource {
@@ -154,8 +368,8 @@ impl TfResource {
         Self {
             resource_name: resource_name.into(),
             resource_identifier: resource_identifier.into(),
-            fields: HashMap::new(),
-            tf_type: "resource".into(),
+            body: TfBody::default(),
+            data_type: TfDataType::Resource,
         }
     }
 
@@ -166,44 +380,65 @@ impl TfResource {
         Self {
             resource_name: resource_name.into(),
             resource_identifier: resource_identifier.into(),
-            fields: HashMap::new(),
-            tf_type: "data".into(),
+            body: TfBody::default(),
+            data_type: TfDataType::Data,
+        }
+    }
+
+    pub fn address(&self) -> TfAddress {
+        TfAddress {
+            data_type: self.data_type,
+            tf_type: self.resource_name.clone(),
+            identifier: self.resource_identifier.clone(),
         }
     }
 
+    /// Sets an attribute, e.g. `name = "value"`
     pub fn add_field(&mut self, field_name: &str, field_value: TfField) -> &mut Self {
-        self.fields.insert(field_name.to_string(), field_value);
+        self.body.set_attribute(field_name, field_value);
 
         self
     }
 
-    pub fn depends_on<T: TerraformEntity>(&mut self, depends_on: &T) -> &mut Self {
-        let var = match T::data_type() {
-            TfDataType::Resource => format!("{}.{}", T::tf_type(), depends_on.tf_identifier()),
-            TfDataType::Data => format!("data.{}.{}", T::tf_type(), depends_on.tf_identifier()),
-        };
+    /// Adds a nested block, e.g. `lifecycle { ... }`
+    pub fn add_block(&mut self, block_type: &str, body: TfBody) -> &mut Self {
+        self.body.add_block(TfBlock::new(block_type, vec![], body));
+
+        self
+    }
+
+    /// Adds to `depends_on`, which fails if it's been set to something other than a list
+    pub fn depends_on<T: TerraformEntity>(
+        &mut self,
+        depends_on: &T,
+    ) -> Result<&mut Self, TfResourceError> {
+        let address = TfField::Address(depends_on.address());
 
-        if let Some(depends_on) = self.fields.get_mut("depends_on") {
-            if let TfField::List(ref mut list) = depends_on {
-                list.push(TfField::Raw(var));
-            } else {
-                panic!("Invalid state for depends_on. Expected a list, got: {depends_on:?}");
+        match self.body.attribute_mut("depends_on") {
+            Some(TfField::List(ref mut list)) => list.push(address),
+            Some(depends_on) => return Err(TfResourceError::InvalidDependsOn(depends_on.clone())),
+            None => {
+                self.add_field("depends_on", TfField::List(vec![address]));
             }
-        } else {
-            self.add_field("depends_on", TfField::List(vec![TfField::Raw(var)]));
         }
 
-        self
+        Ok(self)
     }
 
-    pub fn create_terraform(&self) -> Terraform {
-        let as_map = TfField::Map(self.fields.clone()).to_tf_string();
+    pub fn to_block(&self) -> TfBlock {
+        let block_type = match self.data_type {
+            TfDataType::Resource => "resource",
+            TfDataType::Data => "data",
+        };
 
-        let tf = format!(
-            "{} \"{}\" \"{}\" {as_map}\n",
-            self.tf_type, self.resource_name, self.resource_identifier
-        );
+        TfBlock::new(
+            block_type,
+            vec![self.resource_name.clone(), self.resource_identifier.clone()],
+            self.body.clone(),
+        )
+    }
 
-        Terraform(tf)
+    pub fn create_terraform(&self) -> Terraform {
+        Terraform::from(self.to_block())
     }
 }
@@ -1,3 +1,279 @@
-use crate::{cloud::Cloud, config::api::api_config::ApiConfig};
+use crate::{
+    cloud::{
+        api::{EndpointType, Route},
+        Cloud,
+    },
+    config::api::api_config::ApiConfig,
+    stack::{
+        api::{domain_name::EndpointConfiguration, endpoint::ApiEndpoint, gateway::ApiGateway},
+        lambda::{LambdaFunction, LambdaRuntime},
+    },
+};
 
-pub fn create_api_resources(cloud: &mut Cloud, api: &ApiConfig) {}
+/// Creates the gateway, custom domain and lambda functions that make up this API.
+///
+/// APIs that share a domain will share the same certificate + domain resources.
+pub fn create_api_resources(cloud: &mut Cloud, api: &ApiConfig) {
+    let endpoint_configuration = match api.endpoint_type {
+        EndpointType::Regional => EndpointConfiguration::Regional,
+        EndpointType::Edge => EndpointConfiguration::Edge,
+        EndpointType::Private => EndpointConfiguration::Private {
+            vpc_endpoint_ids: api.vpc_endpoint_ids.clone(),
+        },
+    };
+
+    let domain = match (
+        api.domain.as_deref(),
+        endpoint_configuration.domain_endpoint(),
+    ) {
+        (Some(name), Some(endpoint)) => Some(cloud.domain(name, endpoint)),
+        // Private APIs can't have a custom domain, which is reported when the config is validated
+        _ => None,
+    };
+    let role = cloud.lambda_role();
+
+    // The prefix is a base path on the custom domain. Without one, it's part of each route instead.
+    let (base_path, route_prefix) = match domain {
+        Some(_) => (api.prefix.clone(), Route::default()),
+        None => (Route::default(), api.prefix.clone()),
+    };
+
+    let endpoints = api
+        .endpoints
+        .iter()
+        .map(|endpoint| ApiEndpoint {
+            lambda: LambdaFunction {
+                name: cloud.resource_name_parts(&[&api.name, &endpoint.name]),
+                role: role.clone(),
+                file_path: endpoint.file.to_string_lossy().into_owned(),
+                runtime: LambdaRuntime::NodeJs20,
+                environment_variables: Default::default(),
+                identifiers: cloud.identifiers().clone(),
+                sensitive_outputs: cloud.sensitive_outputs().clone(),
+            },
+            http_method: endpoint.method,
+            route: route_prefix.join(&endpoint.route),
+        })
+        .collect();
+
+    cloud.add_gateway(ApiGateway {
+        name: cloud.resource_name(&api.name),
+        domain,
+        stage_name: cloud.stage_name().to_owned(),
+        base_path,
+        route_prefix,
+        endpoint_configuration,
+        endpoints,
+        identifiers: cloud.identifiers().clone(),
+        sensitive_outputs: cloud.sensitive_outputs().clone(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        cloud::api::HttpMethod,
+        config::{api::api_config::ApiEndpoint as EndpointConfig, loading::diagnostics::SourceRef},
+        stack::tf::{Terraform, TfField},
+    };
+
+    fn api(domain: Option<&str>, prefix: &str) -> ApiConfig {
+        let source = SourceRef {
+            file: PathBuf::from("cloud.toml"),
+            location: None,
+        };
+
+        ApiConfig {
+            name: "main".into(),
+            root: PathBuf::from("api"),
+            domain: domain.map(str::to_owned),
+            prefix: Route::parse(prefix),
+            endpoint_type: EndpointType::Regional,
+            vpc_endpoint_ids: vec![],
+            endpoints: vec![EndpointConfig {
+                name: "get_posts".into(),
+                method: HttpMethod::Get,
+                route: Route::parse("posts"),
+                file: PathBuf::from("api/posts/get.js"),
+                read: vec![],
+                write: vec![],
+                source: source.clone(),
+            }],
+            source,
+        }
+    }
+
+    fn render(api: &ApiConfig) -> Terraform {
+        let mut cloud = Cloud::new(None);
+        create_api_resources(&mut cloud, api);
+
+        cloud.create_terraform().unwrap()
+    }
+
+    fn url(terraform: &Terraform) -> String {
+        terraform
+            .find_block("output", &["gateway_main_url"])
+            .and_then(|x| x.body.attribute("value"))
+            .map(|x| x.to_tf_string())
+            .unwrap()
+    }
+
+    #[test]
+    fn serves_the_prefix_as_a_base_path_on_a_domain() {
+        let terraform = render(&api(Some("example.com"), "api"));
+
+        assert_eq!(
+            url(&terraform),
+            "\"https://${aws_api_gateway_domain_name.domain_example_com.domain_name}/api\""
+        );
+        let mapping = terraform
+            .find_block(
+                "resource",
+                &["aws_api_gateway_base_path_mapping", "gateway_main"],
+            )
+            .unwrap();
+        assert_eq!(
+            mapping.body.attribute("base_path"),
+            Some(&TfField::String("api".into()))
+        );
+        assert!(terraform
+            .find_block(
+                "resource",
+                &["aws_api_gateway_resource", "resource_path_main_posts"]
+            )
+            .is_some());
+    }
+
+    #[test]
+    fn puts_the_prefix_in_every_route_without_a_domain() {
+        let terraform = render(&api(None, "api"));
+
+        // The URL points at the root of the API, like it does on a domain
+        assert_eq!(
+            url(&terraform),
+            "\"${aws_api_gateway_stage.stage_main_prod.invoke_url}/api\""
+        );
+        assert!(terraform
+            .find_block(
+                "resource",
+                &["aws_api_gateway_resource", "resource_path_main_api_posts"]
+            )
+            .is_some());
+        assert!(terraform
+            .blocks
+            .iter()
+            .all(|x| x.labels.first().map(String::as_str)
+                != Some("aws_api_gateway_base_path_mapping")));
+    }
+
+    #[test]
+    fn outputs_the_invoke_url_without_a_domain_or_prefix() {
+        let terraform = render(&api(None, ""));
+
+        assert_eq!(
+            url(&terraform),
+            "aws_api_gateway_stage.stage_main_prod.invoke_url"
+        );
+    }
+
+    #[test]
+    fn shares_a_domain_between_apis() {
+        let mut cloud = Cloud::new(None);
+        create_api_resources(&mut cloud, &api(Some("example.com"), "api"));
+        create_api_resources(
+            &mut cloud,
+            &ApiConfig {
+                name: "secondary".into(),
+                ..api(Some("example.com"), "v2")
+            },
+        );
+        let terraform = cloud.create_terraform().unwrap();
+
+        let count = |tf_type: &str| {
+            terraform
+                .blocks
+                .iter()
+                .filter(|x| x.labels.first().map(String::as_str) == Some(tf_type))
+                .count()
+        };
+        assert_eq!(count("aws_api_gateway_domain_name"), 1);
+        assert_eq!(count("aws_acm_certificate"), 1);
+
+        let base_path = |gateway: &str| {
+            terraform
+                .find_block("resource", &["aws_api_gateway_base_path_mapping", gateway])
+                .and_then(|x| x.body.attribute("base_path"))
+                .cloned()
+        };
+        assert_eq!(
+            base_path("gateway_main"),
+            Some(TfField::String("api".into()))
+        );
+        assert_eq!(
+            base_path("gateway_secondary"),
+            Some(TfField::String("v2".into()))
+        );
+    }
+
+    #[test]
+    fn restricts_private_apis_to_their_vpc_endpoints() {
+        let terraform = render(&ApiConfig {
+            endpoint_type: EndpointType::Private,
+            vpc_endpoint_ids: vec!["vpce-123".into()],
+            ..api(None, "")
+        });
+
+        let gateway = terraform
+            .find_block("resource", &["aws_api_gateway_rest_api", "gateway_main"])
+            .unwrap();
+        let endpoint_configuration = gateway.body.blocks().next().unwrap();
+        assert_eq!(
+            endpoint_configuration.body.attribute("vpc_endpoint_ids"),
+            Some(&TfField::List(vec![TfField::String("vpce-123".into())]))
+        );
+
+        let policy = terraform
+            .find_block(
+                "resource",
+                &["aws_api_gateway_rest_api_policy", "gateway_main"],
+            )
+            .and_then(|x| x.body.attribute("policy"))
+            .map(|x| x.to_inline_hcl())
+            .unwrap();
+        assert!(policy.contains("\"aws:SourceVpce\" = [\"vpce-123\"]"));
+        assert!(policy.contains("Effect = \"Deny\""));
+        assert!(policy.contains("Effect = \"Allow\""));
+    }
+
+    #[test]
+    fn only_private_apis_have_a_policy() {
+        let terraform = render(&api(None, ""));
+
+        assert!(terraform.blocks.iter().all(
+            |x| x.labels.first().map(String::as_str) != Some("aws_api_gateway_rest_api_policy")
+        ));
+    }
+
+    #[test]
+    fn serves_edge_domains_with_a_cloudfront_certificate() {
+        let terraform = render(&ApiConfig {
+            endpoint_type: EndpointType::Edge,
+            ..api(Some("example.com"), "")
+        });
+
+        let domain = terraform
+            .find_block(
+                "resource",
+                &["aws_api_gateway_domain_name", "domain_example_com"],
+            )
+            .unwrap();
+        assert!(domain.body.attribute("certificate_arn").is_some());
+        assert!(domain.body.attribute("regional_certificate_arn").is_none());
+        assert!(terraform
+            .find_block("output", &["domain_example_com_cloudfront_domain_name"])
+            .is_some());
+    }
+}
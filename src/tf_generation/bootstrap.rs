@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use crate::{
+    config::cloud_config::CloudConfig,
+    stack::{
+        region::Provider,
+        settings::TerraformSettings,
+        state::{LockTable, StateBucket},
+        tf::identifier::{slug, stable_hash},
+    },
+};
+
+use super::{
+    files::split_files, manifest::Manifest, terraform_settings, write_files, OutputFormat,
+};
+
+/// S3 bucket names are at most 63 characters
+const MAX_BUCKET_NAME: usize = 63;
+/// DynamoDB table names are at most 255 characters
+const MAX_TABLE_NAME: usize = 255;
+
+/// `<project>[-<env>]-terraform-state`. S3 bucket names are global, so this may need overriding
+/// with `bucket` in `[terraform.backend]` if someone else has already taken it.
+pub fn state_bucket_name(config: &CloudConfig) -> String {
+    resource_name(config, "terraform-state", MAX_BUCKET_NAME)
+}
+
+/// `<project>[-<env>]-terraform-locks`
+pub fn lock_table_name(config: &CloudConfig) -> String {
+    resource_name(config, "terraform-locks", MAX_TABLE_NAME)
+}
+
+fn resource_name(config: &CloudConfig, suffix: &str, max_len: usize) -> String {
+    let mut parts = vec![config.project_name.as_str()];
+    if let Some(environment) = &config.environment {
+        parts.push(environment);
+    }
+
+    // Bucket names may only contain lowercase letters, numbers, `.` and `-`
+    let name = format!("{}-{suffix}", slug(&parts).replace('_', "-"));
+    if name.len() <= max_len {
+        return name;
+    }
+
+    // Only the project name is shortened, so each environment still gets its own name. The hash
+    // keeps projects that start the same apart.
+    let mut rest = format!("-{suffix}");
+    if let Some(environment) = &config.environment {
+        rest = format!("-{}{rest}", slug(&[environment]).replace('_', "-"));
+    }
+
+    let hash = stable_hash(&[&config.project_name]);
+    let project = slug(&[&config.project_name]).replace('_', "-");
+    let keep = max_len.saturating_sub(rest.len() + hash.len() + 1);
+
+    format!(
+        "{}-{hash}{rest}",
+        project[..keep.min(project.len())].trim_end_matches('-')
+    )
+}
+
+/// Writes the terraform for the bucket + lock table the project's S3 backend uses.
+///
+/// This stack keeps its own state locally, since it creates the place the real state goes. Like
+/// [`super::generate`], returns the stale files left in place since they've been edited.
+pub fn generate_bootstrap(
+    config: &CloudConfig,
+    out_dir: &Path,
+    format: OutputFormat,
+) -> anyhow::Result<Vec<String>> {
+    let provider = Provider::default();
+
+    let bucket = StateBucket {
+        name: state_bucket_name(config),
+    };
+    let lock_table = LockTable {
+        name: lock_table_name(config),
+    };
+
+    let settings = TerraformSettings {
+        backend: None,
+        ..terraform_settings(config, provider.region.to_tf_string())
+    };
+
+    let terraform = settings
+        .create_terraform()
+        .combine(&provider.create_terraform())
+        .combine(&bucket.create_terraform())
+        .combine(&lock_table.create_terraform())
+        .in_file("state");
+
+    let previous = Manifest::load(out_dir)?;
+    let files = split_files(&terraform, format)
+        .into_iter()
+        .map(|(name, contents)| (name, contents.into_bytes()))
+        .collect();
+
+    let (written, edited) = write_files(out_dir, &previous, files)?;
+    Manifest::new(&terraform, vec![], written).save(out_dir)?;
+
+    Ok(edited)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(project_name: &str, environment: Option<&str>) -> CloudConfig {
+        CloudConfig {
+            project_name: project_name.into(),
+            environment: environment.map(str::to_owned),
+            api: vec![],
+            database: vec![],
+            outputs: Default::default(),
+            terraform: Default::default(),
+        }
+    }
+
+    #[test]
+    fn names_state_buckets() {
+        assert_eq!(
+            state_bucket_name(&config("My_Project", None)),
+            "my-project-terraform-state"
+        );
+        assert_eq!(
+            state_bucket_name(&config("blog", Some("prod"))),
+            "blog-prod-terraform-state"
+        );
+    }
+
+    #[test]
+    fn shortens_long_project_names_only() {
+        let project = "a-really-long-project-name-that-goes-on-and-on-and-on";
+        let dev = state_bucket_name(&config(project, Some("dev")));
+        let prod = state_bucket_name(&config(project, Some("prod")));
+
+        assert_ne!(dev, prod);
+        for name in [&dev, &prod] {
+            assert!(name.len() <= MAX_BUCKET_NAME, "{name} is too long");
+            assert!(name.starts_with("a-really-long-project"));
+            assert!(name.contains(&stable_hash(&[project])));
+        }
+        assert!(dev.ends_with("-dev-terraform-state"));
+        assert!(prod.ends_with("-prod-terraform-state"));
+
+        // Table names can be longer, so they're left alone
+        assert_eq!(
+            lock_table_name(&config(project, Some("dev"))),
+            format!("{project}-dev-terraform-locks")
+        );
+    }
+}
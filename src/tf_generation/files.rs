@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+
+use crate::stack::tf::{Terraform, TfBlock};
+
+use super::OutputFormat;
+
+/// Resources without a file of their own (see [`Terraform::in_file`]) end up here
+const DEFAULT_FILE: &str = "main";
+
+/// Splits `terraform` into one file per service (`providers`, `iam`, `lambda`, `api_<name>`, ...),
+/// returning each file's name + contents. Blocks keep the order they had in `terraform`.
+pub fn split_files(terraform: &Terraform, format: OutputFormat) -> Vec<(String, String)> {
+    let mut files: BTreeMap<&str, Terraform> = BTreeMap::new();
+
+    for block in terraform.blocks.iter() {
+        let file = service_file(block)
+            .or_else(|| block.address().and_then(|x| terraform.file_of(&x)))
+            .unwrap_or(DEFAULT_FILE);
+
+        files.entry(file).or_default().push(block.clone());
+    }
+
+    files
+        .into_iter()
+        .map(|(name, terraform)| {
+            (
+                format!("{name}{}", format.extension()),
+                format.render(&terraform),
+            )
+        })
+        .collect()
+}
+
+/// The file for blocks that belong together no matter which part of the stack created them
+fn service_file(block: &TfBlock) -> Option<&'static str> {
+    match block.block_type.as_str() {
+        "terraform" | "provider" => return Some("providers"),
+        "output" => return Some("outputs"),
+        "moved" => return Some("moved"),
+        "resource" | "data" => {}
+        _ => return None,
+    }
+
+    match block.labels.first()?.as_str() {
+        x if x.starts_with("aws_lambda_") => Some("lambda"),
+        x if x.starts_with("aws_iam_") => Some("iam"),
+        "aws_acm_certificate" | "aws_api_gateway_domain_name" => Some("domains"),
+        _ => None,
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::Path,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::stack::tf::{Terraform, TfAddress, TfBlock, TfBody, TfDataType, TfField};
+
+/// Kept in the output directory between builds so the next build knows what was generated before
+pub const MANIFEST_FILE: &str = "instacloud-manifest.json";
+
+/// What the last build generated
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub resources: Vec<ManifestResource>,
+    /// Every rename found so far. These are kept (rather than only emitted once) since terraform
+    /// only applies them when it's next run, which may be several builds later.
+    #[serde(default)]
+    pub moved: Vec<Move>,
+    /// Every file written to the output directory, so files that are no longer generated can be
+    /// removed without touching anything else in there
+    #[serde(default)]
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// Relative to the output directory
+    pub path: String,
+    /// Hex sha256 of the contents
+    pub sha256: String,
+}
+
+impl ManifestFile {
+    pub fn new(path: impl Into<String>, contents: &[u8]) -> Self {
+        Self {
+            path: path.into(),
+            sha256: format!("{:x}", Sha256::digest(contents)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestResource {
+    pub address: TfAddress,
+    pub logical_keys: Vec<String>,
+    /// Every attribute as it was written, e.g. `"runtime": "\"nodejs20.x\""`. Nested blocks'
+    /// attributes are prefixed with the block type, e.g. `lifecycle.prevent_destroy`.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl ManifestResource {
+    fn new(block: &TfBlock, address: TfAddress, logical_keys: Vec<String>) -> Self {
+        let mut attributes = BTreeMap::new();
+        add_attributes(&mut attributes, "", &block.body);
+
+        Self {
+            address,
+            logical_keys,
+            attributes,
+        }
+    }
+}
+
+fn add_attributes(attributes: &mut BTreeMap<String, String>, prefix: &str, body: &TfBody) {
+    for (name, value) in body.attributes() {
+        attributes.insert(format!("{prefix}{name}"), value.to_inline_hcl());
+    }
+
+    for block in body.blocks() {
+        let mut prefix = format!("{prefix}{}.", block.block_type);
+        for label in block.labels.iter() {
+            prefix.push_str(&format!("{label}."));
+        }
+        add_attributes(attributes, &prefix, &block.body);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Move {
+    pub from: TfAddress,
+    pub to: TfAddress,
+}
+
+impl Move {
+    /// `moved { from = ... to = ... }`
+    pub fn to_block(&self) -> TfBlock {
+        let mut body = TfBody::new();
+        body.set_attribute("from", TfField::Address(self.from.clone()))
+            .set_attribute("to", TfField::Address(self.to.clone()));
+
+        TfBlock::new("moved", vec![], body)
+    }
+}
+
+impl Manifest {
+    /// Loads the manifest from `out_dir`, or an empty one if nothing has been generated there yet
+    pub fn load(out_dir: &Path) -> anyhow::Result<Self> {
+        let path = out_dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Unable to parse {}", path.display()))
+    }
+
+    pub fn save(&self, out_dir: &Path) -> anyhow::Result<()> {
+        let path = out_dir.join(MANIFEST_FILE);
+        fs::write(&path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Unable to write {}", path.display()))
+    }
+
+    pub fn new(terraform: &Terraform, moved: Vec<Move>, files: Vec<ManifestFile>) -> Self {
+        let resources = terraform
+            .blocks
+            .iter()
+            .filter_map(|block| Some((block, block.address()?)))
+            .filter(|(_, address)| address.data_type == TfDataType::Resource)
+            .map(|(block, address)| {
+                ManifestResource::new(block, address.clone(), terraform.logical_keys(&address))
+            })
+            .collect();
+
+        Self {
+            resources,
+            moved,
+            files,
+        }
+    }
+
+    /// Removes the files generated last time that aren't in `current`. Files that have been edited
+    /// since they were generated are left alone, and returned.
+    pub fn remove_stale_files(
+        &self,
+        out_dir: &Path,
+        current: &[ManifestFile],
+    ) -> io::Result<Vec<String>> {
+        let mut edited = vec![];
+
+        for file in self.files.iter() {
+            if current.iter().any(|x| x.path == file.path) {
+                continue;
+            }
+
+            let path = out_dir.join(&file.path);
+            let contents = match fs::read(&path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+
+            if ManifestFile::new(&file.path, &contents) != *file {
+                edited.push(file.path.clone());
+                continue;
+            }
+
+            fs::remove_file(path)?;
+        }
+
+        Ok(edited)
+    }
+
+    /// Works out which resources in `terraform` are resources from the last build under a new
+    /// identifier.
+    ///
+    /// A new resource is a renamed old one if they're the same type, share a logical key, and
+    /// neither could be matched up with anything else - anything ambiguous is left to be replaced.
+    pub fn find_moves(&self, terraform: &Terraform) -> Vec<Move> {
+        let current = terraform
+            .blocks
+            .iter()
+            .filter_map(|x| x.address())
+            .collect::<BTreeSet<_>>();
+        let previous = self
+            .resources
+            .iter()
+            .map(|x| x.address.clone())
+            .collect::<BTreeSet<_>>();
+
+        let removed = self
+            .resources
+            .iter()
+            .filter(|x| !current.contains(&x.address))
+            .collect::<Vec<_>>();
+        let added = current
+            .iter()
+            .filter(|x| x.data_type == TfDataType::Resource && !previous.contains(x))
+            .map(|x| (x, terraform.logical_keys(x)))
+            .collect::<Vec<_>>();
+
+        let matches = |old: &ManifestResource, new: &TfAddress, keys: &[String]| {
+            old.address.tf_type == new.tf_type && old.logical_keys.iter().any(|x| keys.contains(x))
+        };
+
+        let mut moves = vec![];
+        for (new, keys) in added.iter() {
+            let candidates = removed
+                .iter()
+                .filter(|old| matches(old, new, keys))
+                .collect::<Vec<_>>();
+            let [old] = candidates.as_slice() else {
+                continue;
+            };
+
+            let claimed_by = added
+                .iter()
+                .filter(|(other, other_keys)| matches(old, other, other_keys))
+                .count();
+            if claimed_by == 1 {
+                moves.push(Move {
+                    from: old.address.clone(),
+                    to: (*new).clone(),
+                });
+            }
+        }
+
+        self.carry_over_moves(&current, moves)
+    }
+
+    /// Keeps earlier moves that still lead to a current resource, so a rename that hasn't been
+    /// applied yet isn't lost by building again
+    fn carry_over_moves(&self, current: &BTreeSet<TfAddress>, new_moves: Vec<Move>) -> Vec<Move> {
+        let moves = self
+            .moved
+            .iter()
+            // A resource was created again at the old address, so moving it would clobber that
+            .filter(|x| !current.contains(&x.from))
+            .chain(new_moves.iter())
+            .map(|x| (x.from.clone(), x.to.clone()))
+            .collect::<BTreeMap<_, _>>();
+
+        // Drop moves that don't end up at a current resource, following chains of moves
+        let leads_to_current = |to: &TfAddress| {
+            let mut at = to;
+            for _ in 0..=moves.len() {
+                if current.contains(at) {
+                    return true;
+                }
+                match moves.get(at) {
+                    Some(next) => at = next,
+                    None => return false,
+                }
+            }
+            // Went round in a loop
+            false
+        };
+
+        moves
+            .iter()
+            .filter(|(_, to)| leads_to_current(to))
+            .map(|(from, to)| Move {
+                from: from.clone(),
+                to: to.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::stack::tf::TfResource;
+
+    /// Roles with these identifiers + logical keys
+    fn roles(roles: &[(&str, &str)]) -> Terraform {
+        roles
+            .iter()
+            .map(|(identifier, key)| {
+                TfResource::new_resource("aws_iam_role", *identifier)
+                    .create_terraform()
+                    .identified_by(&[key.to_string()])
+            })
+            .fold(Terraform::default(), |a, b| a.combine(&b))
+    }
+
+    fn role(identifier: &str) -> TfAddress {
+        TfResource::new_resource("aws_iam_role", identifier).address()
+    }
+
+    fn moved(from: &str, to: &str) -> Move {
+        Move {
+            from: role(from),
+            to: role(to),
+        }
+    }
+
+    fn manifest(terraform: &Terraform, moved: Vec<Move>) -> Manifest {
+        Manifest::new(terraform, moved, vec![])
+    }
+
+    #[test]
+    fn finds_renamed_resources() {
+        let previous = manifest(&roles(&[("old", "role:a"), ("kept", "role:b")]), vec![]);
+
+        let moves = previous.find_moves(&roles(&[("new", "role:a"), ("kept", "role:b")]));
+        assert_eq!(moves, vec![moved("old", "new")]);
+    }
+
+    #[test]
+    fn only_moves_to_the_same_type() {
+        let previous = manifest(&roles(&[("old", "role:a")]), vec![]);
+        let current = TfResource::new_resource("aws_lambda_function", "new")
+            .create_terraform()
+            .identified_by(&["role:a".into()]);
+
+        assert_eq!(previous.find_moves(&current), vec![]);
+    }
+
+    #[test]
+    fn leaves_ambiguous_renames_alone() {
+        // Two old resources could have become the new one
+        let previous = manifest(&roles(&[("old_1", "role:a"), ("old_2", "role:a")]), vec![]);
+        assert_eq!(previous.find_moves(&roles(&[("new", "role:a")])), vec![]);
+
+        // The old resource could have become either new one
+        let previous = manifest(&roles(&[("old", "role:a")]), vec![]);
+        assert_eq!(
+            previous.find_moves(&roles(&[("new_1", "role:a"), ("new_2", "role:a")])),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn keeps_earlier_moves_that_lead_somewhere() {
+        // `first` was renamed to `second` last time, + is now renamed again to `third`
+        let previous = manifest(
+            &roles(&[("second", "role:a"), ("other", "role:b")]),
+            vec![moved("first", "second"), moved("gone", "other")],
+        );
+
+        let moves = previous.find_moves(&roles(&[("third", "role:a")]));
+        assert_eq!(
+            moves,
+            vec![moved("first", "second"), moved("second", "third")]
+        );
+    }
+
+    #[test]
+    fn drops_moves_to_recreated_addresses() {
+        let previous = manifest(&roles(&[("new", "role:a")]), vec![moved("old", "new")]);
+
+        // A resource is back at `old`, so moving it would replace that one
+        let moves = previous.find_moves(&roles(&[("old", "role:b"), ("new", "role:a")]));
+        assert_eq!(moves, vec![]);
+    }
+
+    #[test]
+    fn removes_stale_files_unless_edited() {
+        let dir = env::temp_dir().join(format!("instacloud-stale-files-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let files = ["current.tf", "stale.tf", "edited.tf", "deleted.tf"]
+            .map(|path| ManifestFile::new(path, b"generated"));
+        for file in files.iter().take(3) {
+            fs::write(dir.join(&file.path), "generated").unwrap();
+        }
+        fs::write(dir.join("edited.tf"), "edited by hand").unwrap();
+        fs::write(dir.join("unrelated.txt"), "not generated").unwrap();
+
+        let previous = Manifest::new(&Terraform::default(), vec![], files.to_vec());
+        let edited = previous
+            .remove_stale_files(&dir, &[ManifestFile::new("current.tf", b"generated")])
+            .unwrap();
+
+        assert_eq!(edited, vec!["edited.tf"]);
+        assert!(dir.join("current.tf").exists());
+        assert!(!dir.join("stale.tf").exists());
+        assert!(dir.join("edited.tf").exists());
+        assert!(dir.join("unrelated.txt").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
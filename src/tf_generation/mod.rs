@@ -1,26 +1,203 @@
-use std::fs;
+use std::{fs, io, path::Path};
 
-use walkdir::WalkDir;
+use api::create_api_resources;
+use bootstrap::{lock_table_name, state_bucket_name};
+use files::split_files;
+use manifest::{Manifest, ManifestFile};
+use thiserror::Error;
+
+use crate::{
+    cloud::Cloud,
+    config::{cloud_config::CloudConfig, terraform::BackendConfig},
+    stack::{
+        registry::StackError,
+        settings::{Backend, TerraformSettings},
+        tf::{SensitiveOutputs, Terraform},
+    },
+};
 
 mod api;
+pub mod bootstrap;
+pub mod files;
+pub mod manifest;
+pub mod plan;
+
+/// The syntax the generated terraform is written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// `.tf` files
+    #[default]
+    Hcl,
+    /// `.tf.json` files, which are easier for other tools to read
+    Json,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Hcl => ".tf",
+            Self::Json => ".tf.json",
+        }
+    }
+
+    pub fn render(&self, terraform: &Terraform) -> String {
+        match self {
+            Self::Hcl => terraform.to_string(),
+            Self::Json => terraform.to_json_string(),
+        }
+    }
+}
+
+/// Builds every stack resource described by the config
+pub fn create_cloud(config: &CloudConfig) -> Cloud {
+    let mut cloud = Cloud::new(config.environment.clone());
+
+    let settings = terraform_settings(config, cloud.region());
+    cloud.set_terraform_settings(settings);
+    cloud.set_sensitive_outputs(SensitiveOutputs::new(
+        config.outputs.sensitive.iter().cloned(),
+    ));
+
+    for api in config.api.iter() {
+        create_api_resources(&mut cloud, api);
+    }
+
+    cloud
+}
+
+#[derive(Debug, Error)]
+pub enum GenerationError {
+    #[error(transparent)]
+    Stack(#[from] StackError),
+    #[error("`{0}` in [outputs] sensitive isn't one of the generated outputs")]
+    UnknownOutput(String),
+}
+
+fn terraform_settings(config: &CloudConfig, region: &str) -> TerraformSettings {
+    let terraform = &config.terraform;
+
+    let backend = terraform.backend.as_ref().map(|backend| match backend {
+        BackendConfig::Local { path } => Backend::Local { path: path.clone() },
+        BackendConfig::S3 {
+            bucket,
+            key,
+            region: backend_region,
+            dynamodb_table,
+        } => Backend::S3 {
+            // Without a bucket, use the bucket + lock table from `instacloud bootstrap`
+            bucket: bucket.clone().unwrap_or_else(|| state_bucket_name(config)),
+            dynamodb_table: match bucket {
+                Some(_) => dynamodb_table.clone(),
+                None => Some(
+                    dynamodb_table
+                        .clone()
+                        .unwrap_or_else(|| lock_table_name(config)),
+                ),
+            },
+            // Each environment needs its own state
+            key: key.clone().unwrap_or_else(|| match &config.environment {
+                Some(environment) => format!("{environment}/terraform.tfstate"),
+                None => "terraform.tfstate".into(),
+            }),
+            region: backend_region.clone().unwrap_or_else(|| region.into()),
+        },
+    });
+
+    TerraformSettings {
+        required_version: terraform.required_version.clone(),
+        aws_provider_version: terraform.aws_provider_version.clone(),
+        backend,
+    }
+}
+
+/// Builds the terraform for this config, making sure no two resources ended up with the same name
+pub fn create_terraform(config: &CloudConfig) -> Result<(Cloud, Terraform), GenerationError> {
+    let cloud = create_cloud(config);
+    let terraform = cloud.create_terraform()?;
 
-fn prepare_gen() {
-    // Removes all old generated files w/out deleting the current terraform state.
-    let delete_extensions = ["zip", "tf"];
-    for entry in WalkDir::new("terraform/generated") {
-        let Ok(entry) = entry else {
-            continue;
-        };
-
-        if entry
-            .path()
-            .extension()
-            .map(|x| delete_extensions.contains(&x.to_str().unwrap_or("")))
-            .unwrap_or(false)
-        {
-            fs::remove_file(entry.path()).expect("Unable to remove old tf file.");
+    // Outputs are marked sensitive as they're created, so this only has to catch typos
+    for name in cloud.sensitive_outputs().iter() {
+        if terraform.find_block("output", &[name]).is_none() {
+            return Err(GenerationError::UnknownOutput(name.clone()));
         }
     }
 
-    fs::create_dir_all("terraform/generated").expect("Unable to create generated dir!");
+    Ok((cloud, terraform))
+}
+
+/// Writes the terraform (split up by service) + lambda zips for this config into `out_dir`.
+///
+/// Returns the files from the last build that are no longer generated but were left in place
+/// since they've been edited.
+pub fn generate(
+    config: &CloudConfig,
+    out_dir: &Path,
+    format: OutputFormat,
+) -> anyhow::Result<Vec<String>> {
+    let previous = Manifest::load(out_dir)?;
+    let (cloud, mut terraform) = create_terraform(config)?;
+
+    // Renamed resources are moved to their new identifier rather than replaced
+    let moves = previous.find_moves(&terraform);
+    for moved in moves.iter() {
+        terraform.push(moved.to_block());
+    }
+
+    let mut files = cloud.zip_lambdas()?;
+    files.extend(
+        split_files(&terraform, format)
+            .into_iter()
+            .map(|(name, contents)| (name, contents.into_bytes())),
+    );
+
+    let (written, edited) = write_files(out_dir, &previous, files)?;
+    Manifest::new(&terraform, moves, written).save(out_dir)?;
+
+    Ok(edited)
+}
+
+/// Writes `files` (name + contents) into `out_dir`, removing anything generated last time that
+/// isn't one of them. Returns the files written, and the stale files left since they'd been
+/// edited.
+fn write_files(
+    out_dir: &Path,
+    previous: &Manifest,
+    files: Vec<(String, Vec<u8>)>,
+) -> anyhow::Result<(Vec<ManifestFile>, Vec<String>)> {
+    let manifest_files = files
+        .iter()
+        .map(|(name, contents)| ManifestFile::new(name, contents))
+        .collect::<Vec<_>>();
+
+    let edited = prepare_gen(out_dir, previous, &manifest_files)?;
+
+    for (name, contents) in files {
+        fs::write(out_dir.join(name), contents)?;
+    }
+
+    Ok((manifest_files, edited))
+}
+
+/// Removes all old generated files w/out deleting the current terraform state. Only files listed
+/// in the manifest are removed, so without one nothing is.
+///
+/// Returns the generated files that were left in place since they've been edited.
+pub fn clean(out_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let manifest = Manifest::load(out_dir)?;
+
+    Ok(manifest.remove_stale_files(out_dir, &[])?)
+}
+
+/// Removes the files from the last build that won't be generated again, leaving everything else
+/// (the terraform state, `.terraform`, hand-written terraform, ...) alone
+fn prepare_gen(
+    out_dir: &Path,
+    previous: &Manifest,
+    current: &[ManifestFile],
+) -> io::Result<Vec<String>> {
+    let edited = previous.remove_stale_files(out_dir, current)?;
+
+    fs::create_dir_all(out_dir)?;
+
+    Ok(edited)
 }
@@ -0,0 +1,534 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::Path,
+};
+
+use crate::{config::cloud_config::CloudConfig, stack::tf::TfAddress};
+
+use super::{
+    create_terraform,
+    manifest::{Manifest, ManifestFile, ManifestResource},
+};
+
+/// Attributes the AWS provider can't change in place, so changing them destroys the resource and
+/// creates a new one
+fn forces_replacement(tf_type: &str, attribute: &str) -> bool {
+    let attributes: &[&str] = match tf_type {
+        "aws_lambda_function" => &["function_name"],
+        "aws_lambda_permission" => &[
+            "statement_id",
+            "action",
+            "function_name",
+            "principal",
+            "source_arn",
+        ],
+        "aws_iam_role" => &["name"],
+        "aws_api_gateway_resource" => &["rest_api_id", "parent_id", "path_part"],
+        "aws_api_gateway_method" | "aws_api_gateway_integration" => {
+            &["rest_api_id", "resource_id", "http_method"]
+        }
+        "aws_api_gateway_deployment" => &["rest_api_id", "triggers"],
+        "aws_api_gateway_stage" => &["rest_api_id", "stage_name"],
+        "aws_api_gateway_rest_api_policy" => &["rest_api_id"],
+        "aws_api_gateway_base_path_mapping" => &["domain_name"],
+        "aws_api_gateway_domain_name" => &["domain_name"],
+        "aws_s3_bucket" => &["bucket"],
+        "aws_dynamodb_table" => &["name", "hash_key"],
+        _ => &[],
+    };
+
+    attributes.contains(&attribute)
+}
+
+/// What the next `terraform apply` would do to the resources instacloud generates, worked out by
+/// comparing a build with the last one written to the output directory.
+///
+/// This only sees the generated config, not what's actually deployed, so changes made outside
+/// instacloud (or values only known once applied) won't show up.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub changes: Vec<ResourceChange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResourceChange {
+    pub address: TfAddress,
+    /// Where the resource was before, if it's being renamed with a `moved` block
+    pub moved_from: Option<TfAddress>,
+    pub action: Action,
+    pub attributes: Vec<AttributeChange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    Create,
+    Update,
+    Replace,
+    Destroy,
+    /// Moved to a new address without any other changes
+    Move,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttributeChange {
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub forces_replacement: bool,
+}
+
+/// Builds the config and compares it with the last build in `out_dir`, without writing anything
+pub fn plan(config: &CloudConfig, out_dir: &Path) -> anyhow::Result<Plan> {
+    let previous = Manifest::load(out_dir)?;
+    let (cloud, terraform) = create_terraform(config)?;
+
+    let moves = previous.find_moves(&terraform);
+    let files = cloud
+        .zip_lambdas()?
+        .iter()
+        .map(|(name, contents)| ManifestFile::new(name, contents))
+        .collect();
+
+    Ok(Plan::new(
+        &previous,
+        &Manifest::new(&terraform, moves, files),
+    ))
+}
+
+impl Plan {
+    pub fn new(previous: &Manifest, current: &Manifest) -> Self {
+        let moves = current
+            .moved
+            .iter()
+            .map(|x| (&x.from, &x.to))
+            .collect::<BTreeMap<_, _>>();
+
+        // Where each of the last build's resources is now, following any renames
+        let previous_by_address = previous
+            .resources
+            .iter()
+            .map(|x| (final_address(&x.address, &moves), x))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut changes = vec![];
+        for resource in current.resources.iter() {
+            let Some(old) = previous_by_address.get(&resource.address) else {
+                changes.push(ResourceChange::new(
+                    resource.address.clone(),
+                    Action::Create,
+                ));
+                continue;
+            };
+
+            let attributes = changed_attributes(old, resource, &moves, previous, current);
+            let action = if attributes.iter().any(|x| x.forces_replacement) {
+                Action::Replace
+            } else if !attributes.is_empty() {
+                Action::Update
+            } else if old.address != resource.address {
+                Action::Move
+            } else {
+                continue;
+            };
+
+            changes.push(ResourceChange {
+                address: resource.address.clone(),
+                moved_from: (old.address != resource.address).then(|| old.address.clone()),
+                action,
+                attributes,
+            });
+        }
+
+        let current_addresses = current
+            .resources
+            .iter()
+            .map(|x| &x.address)
+            .collect::<Vec<_>>();
+        for (address, old) in previous_by_address.iter() {
+            if !current_addresses.contains(address) {
+                changes.push(ResourceChange::new(old.address.clone(), Action::Destroy));
+            }
+        }
+
+        Self { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    fn count(&self, action: Action) -> usize {
+        self.changes.iter().filter(|x| x.action == action).count()
+    }
+}
+
+impl ResourceChange {
+    fn new(address: TfAddress, action: Action) -> Self {
+        Self {
+            address,
+            moved_from: None,
+            action,
+            attributes: vec![],
+        }
+    }
+}
+
+fn changed_attributes(
+    old: &ManifestResource,
+    new: &ManifestResource,
+    moves: &BTreeMap<&TfAddress, &TfAddress>,
+    previous: &Manifest,
+    current: &Manifest,
+) -> Vec<AttributeChange> {
+    // Built before attributes were recorded, so there's nothing to compare with
+    if old.attributes.is_empty() {
+        return vec![];
+    }
+
+    let mut names = old.attributes.keys().collect::<Vec<_>>();
+    names.extend(
+        new.attributes
+            .keys()
+            .filter(|x| !old.attributes.contains_key(*x)),
+    );
+
+    let mut changes = vec![];
+    for name in names {
+        // References to renamed resources read the same once the rename is applied
+        let before = old.attributes.get(name).map(|x| apply_moves(x, moves));
+        let after = new.attributes.get(name).cloned();
+
+        if before != after {
+            changes.push(AttributeChange {
+                forces_replacement: forces_replacement(&new.address.tf_type, name),
+                name: name.clone(),
+                before,
+                after,
+            });
+        }
+    }
+
+    // The zip's name stays the same when a handler changes, so compare what's in it
+    if let Some(filename) = new.attributes.get("filename") {
+        let hash = |manifest: &Manifest| {
+            manifest
+                .files
+                .iter()
+                .find(|x| format!("\"{}\"", x.path) == *filename)
+                .map(|x| x.sha256.clone())
+        };
+
+        let (before, after) = (hash(previous), hash(current));
+        if before.is_some() && before != after {
+            changes.push(AttributeChange {
+                name: "source_code_hash".into(),
+                before: before.map(|x| format!("(sha256 {x})")),
+                after: after.map(|x| format!("(sha256 {x})")),
+                forces_replacement: false,
+            });
+        }
+    }
+
+    changes
+}
+
+/// Where the resource at `address` ends up after `moves`, following chains of moves (`a` -> `b`
+/// -> `c`). A move back to an address it's already been at (e.g. a swap) isn't followed, so it
+/// can't go round in circles.
+fn final_address<'a>(
+    address: &'a TfAddress,
+    moves: &BTreeMap<&'a TfAddress, &'a TfAddress>,
+) -> &'a TfAddress {
+    let mut visited = BTreeSet::from([address]);
+    let mut at = address;
+
+    while let Some(next) = moves.get(at) {
+        if !visited.insert(*next) {
+            break;
+        }
+        at = next;
+    }
+
+    at
+}
+
+/// Rewrites references to moved resources in `value` (an HCL expression) to their
+/// [`final_address`]. Each reference is only rewritten once, so a swap or a chain of moves can't
+/// rewrite the result again.
+fn apply_moves(value: &str, moves: &BTreeMap<&TfAddress, &TfAddress>) -> String {
+    let moved = moves
+        .keys()
+        .map(|from| (from.to_string(), *from))
+        .collect::<Vec<_>>();
+
+    let mut result = String::with_capacity(value.len());
+    let mut i = 0;
+
+    while let Some(c) = value[i..].chars().next() {
+        // Only whole addresses, so `aws_x.a` doesn't match `aws_x.a_b` or `data.aws_x.a`
+        let starts_address = !value[..i].ends_with(|x| is_identifier_char(x) || x == '.');
+        let found = moved.iter().find(|(from, _)| {
+            starts_address
+                && value[i..].starts_with(from.as_str())
+                && !value[i + from.len()..].starts_with(is_identifier_char)
+        });
+
+        match found {
+            Some((from, address)) => {
+                result.push_str(&final_address(address, moves).to_string());
+                i += from.len();
+            }
+            None => {
+                result.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+
+    result
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Create => "+",
+            Self::Update => "~",
+            Self::Replace => "-/+",
+            Self::Destroy => "-",
+            Self::Move => "->",
+        })
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes since the last build.");
+        }
+
+        let mut changes = self.changes.iter().collect::<Vec<_>>();
+        changes.sort_by(|a, b| (a.action, &a.address).cmp(&(b.action, &b.address)));
+
+        for change in changes {
+            let description = match change.action {
+                Action::Create => "will be created",
+                Action::Update => "will be updated in-place",
+                Action::Replace => "must be replaced",
+                Action::Destroy => "will be destroyed",
+                Action::Move => "has moved",
+            };
+            write!(f, "{} {} {description}", change.action, change.address)?;
+            match &change.moved_from {
+                Some(from) => writeln!(f, " (from {from})")?,
+                None => writeln!(f)?,
+            }
+
+            for attribute in change.attributes.iter() {
+                let before = attribute.before.as_deref().unwrap_or("(none)");
+                let after = attribute.after.as_deref().unwrap_or("(none)");
+                let forces = if attribute.forces_replacement {
+                    " # forces replacement"
+                } else {
+                    ""
+                };
+                writeln!(f, "    {}: {before} -> {after}{forces}", attribute.name)?;
+            }
+        }
+
+        writeln!(
+            f,
+            "\nPlan: {} to add, {} to change, {} to replace, {} to destroy, {} to move.",
+            self.count(Action::Create),
+            self.count(Action::Update),
+            self.count(Action::Replace),
+            self.count(Action::Destroy),
+            self.changes
+                .iter()
+                .filter(|x| x.moved_from.is_some())
+                .count(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{stack::tf::TfDataType, tf_generation::manifest::Move};
+
+    fn address(tf_type: &str, identifier: &str) -> TfAddress {
+        TfAddress {
+            data_type: TfDataType::Resource,
+            tf_type: tf_type.into(),
+            identifier: identifier.into(),
+        }
+    }
+
+    fn resource(address: TfAddress, attributes: &[(&str, &str)]) -> ManifestResource {
+        ManifestResource {
+            address,
+            logical_keys: vec![],
+            attributes: attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn manifest(resources: Vec<ManifestResource>) -> Manifest {
+        Manifest {
+            resources,
+            ..Default::default()
+        }
+    }
+
+    fn lambda(identifier: &str, attributes: &[(&str, &str)]) -> ManifestResource {
+        resource(address("aws_lambda_function", identifier), attributes)
+    }
+
+    #[test]
+    fn finds_created_and_destroyed_resources() {
+        let previous = manifest(vec![lambda("old", &[("runtime", "\"nodejs20.x\"")])]);
+        let current = manifest(vec![lambda("new", &[("runtime", "\"nodejs20.x\"")])]);
+
+        let plan = Plan::new(&previous, &current);
+        let actions = plan
+            .changes
+            .iter()
+            .map(|x| (x.address.identifier.as_str(), x.action))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            actions,
+            vec![("new", Action::Create), ("old", Action::Destroy)]
+        );
+        assert!(Plan::new(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn tells_updates_from_replacements() {
+        let previous = manifest(vec![lambda(
+            "main",
+            &[("function_name", "\"a\""), ("runtime", "\"nodejs18.x\"")],
+        )]);
+
+        let updated = manifest(vec![lambda(
+            "main",
+            &[("function_name", "\"a\""), ("runtime", "\"nodejs20.x\"")],
+        )]);
+        let plan = Plan::new(&previous, &updated);
+        assert_eq!(plan.changes[0].action, Action::Update);
+        assert_eq!(plan.changes[0].attributes[0].name, "runtime");
+        assert!(!plan.changes[0].attributes[0].forces_replacement);
+
+        let replaced = manifest(vec![lambda(
+            "main",
+            &[("function_name", "\"b\""), ("runtime", "\"nodejs18.x\"")],
+        )]);
+        let plan = Plan::new(&previous, &replaced);
+        assert_eq!(plan.changes[0].action, Action::Replace);
+        assert!(plan.changes[0].attributes[0].forces_replacement);
+    }
+
+    #[test]
+    fn follows_moves() {
+        let role = |identifier: &str| address("aws_iam_role", identifier);
+        let previous = manifest(vec![
+            resource(role("old"), &[("name", "\"LambdaRole\"")]),
+            lambda("main", &[("role", "aws_iam_role.old.arn")]),
+        ]);
+        let mut current = manifest(vec![
+            resource(role("new"), &[("name", "\"LambdaRole\"")]),
+            lambda("main", &[("role", "aws_iam_role.new.arn")]),
+        ]);
+        current.moved = vec![Move {
+            from: role("old"),
+            to: role("new"),
+        }];
+
+        // The lambda's reference is rewritten to the new address, so only the role changes
+        let plan = Plan::new(&previous, &current);
+        assert_eq!(plan.changes.len(), 1);
+        assert_eq!(plan.changes[0].action, Action::Move);
+        assert_eq!(plan.changes[0].moved_from, Some(role("old")));
+    }
+
+    #[test]
+    fn rewrites_whole_references_only() {
+        let from = address("aws_iam_role", "role");
+        let to = address("aws_iam_role", "role_2");
+        let moves = BTreeMap::from([(&from, &to)]);
+
+        assert_eq!(
+            apply_moves("[aws_iam_role.role.arn, aws_iam_role.role_x.arn]", &moves),
+            "[aws_iam_role.role_2.arn, aws_iam_role.role_x.arn]"
+        );
+    }
+
+    #[test]
+    fn rewrites_swapped_references() {
+        let a = address("aws_iam_role", "a");
+        let b = address("aws_iam_role", "b");
+        let moves = BTreeMap::from([(&a, &b), (&b, &a)]);
+
+        assert_eq!(
+            apply_moves("[aws_iam_role.a.arn, aws_iam_role.b.arn]", &moves),
+            "[aws_iam_role.b.arn, aws_iam_role.a.arn]"
+        );
+    }
+
+    #[test]
+    fn rewrites_chained_references() {
+        // Both orders, so it doesn't matter which way the addresses sort
+        let (a, b, c) = (
+            address("aws_iam_role", "a"),
+            address("aws_iam_role", "b"),
+            address("aws_iam_role", "c"),
+        );
+        let forwards = BTreeMap::from([(&a, &b), (&b, &c)]);
+        assert_eq!(
+            apply_moves("aws_iam_role.a.arn", &forwards),
+            "aws_iam_role.c.arn"
+        );
+
+        let backwards = BTreeMap::from([(&c, &b), (&b, &a)]);
+        assert_eq!(
+            apply_moves("aws_iam_role.c.arn", &backwards),
+            "aws_iam_role.a.arn"
+        );
+    }
+
+    #[test]
+    fn follows_chained_moves() {
+        let role = |identifier: &str| address("aws_iam_role", identifier);
+        let previous = manifest(vec![
+            resource(role("c"), &[("name", "\"LambdaRole\"")]),
+            lambda("main", &[("role", "aws_iam_role.c.arn")]),
+        ]);
+        let mut current = manifest(vec![
+            resource(role("a"), &[("name", "\"LambdaRole\"")]),
+            lambda("main", &[("role", "aws_iam_role.a.arn")]),
+        ]);
+        // Carried over from a build that was never applied, then renamed again
+        current.moved = vec![
+            Move {
+                from: role("c"),
+                to: role("b"),
+            },
+            Move {
+                from: role("b"),
+                to: role("a"),
+            },
+        ];
+
+        let plan = Plan::new(&previous, &current);
+        assert_eq!(plan.changes.len(), 1);
+        assert_eq!(plan.changes[0].action, Action::Move);
+        assert_eq!(plan.changes[0].address, role("a"));
+        assert_eq!(plan.changes[0].moved_from, Some(role("c")));
+    }
+}